@@ -0,0 +1,22 @@
+/// Kafka message doesn't follow signer protocol
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ProtocolError {
+    #[error("expected headers in msg")]
+    MissingHeaders,
+    #[error("missing `{0}` header")]
+    MissingHeader(&'static str),
+    #[error("`{0}` header is present more than once")]
+    DuplicateHeader(&'static str),
+    #[error("value of `{0}` header is not valid UTF-8")]
+    InvalidHeaderValue(&'static str),
+    #[error("malformed value `{value}` of `{header}` header")]
+    MalformedHeaderValue { header: &'static str, value: String },
+    #[error("no payload")]
+    MissingPayload,
+    #[error("malformed {0} payload: {1}")]
+    MalformedPayload(&'static str, String),
+    #[error("payload written with unexpected schema id {0}")]
+    UnknownSchemaId(u32),
+    #[error("`msg_id` header doesn't match `msg_id` in payload")]
+    MsgIdMismatch,
+}
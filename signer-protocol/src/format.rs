@@ -0,0 +1,378 @@
+//! Payload formats of `signer.v1` messages
+//!
+//! With [`PayloadFormat::Raw`] payload is the message to sign (or the signature) as is. Record
+//! formats wrap the payload together with message metadata into Avro or Protobuf record written in
+//! Confluent wire format, so schema-aware consumers can read the topics. Headers are sent in every
+//! format.
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use apache_avro::types::Value;
+use apache_avro::Schema;
+use rdkafka::Message;
+
+use crate::schema_registry::{RegistryError, SchemaRegistry, SchemaType};
+use crate::{wire, MsgFailed, MsgResp, MsgSigned, MsgToSign, ProtocolError};
+
+/// Subjects follow `RecordNameStrategy`
+pub const REQUEST_SUBJECT: &str = "signer.v1.SignRequest";
+pub const RESPONSE_SUBJECT: &str = "signer.v1.SignResponse";
+
+pub const REQUEST_AVRO_SCHEMA: &str = r#"{
+  "type": "record",
+  "name": "SignRequest",
+  "namespace": "signer.v1",
+  "fields": [
+    { "name": "msg_id", "type": "string" },
+    { "name": "message", "type": "bytes" }
+  ]
+}"#;
+
+pub const RESPONSE_AVRO_SCHEMA: &str = r#"{
+  "type": "record",
+  "name": "SignResponse",
+  "namespace": "signer.v1",
+  "fields": [
+    { "name": "msg_id", "type": "string" },
+    { "name": "resp_id", "type": "string" },
+    { "name": "key_id", "type": "string" },
+    { "name": "signature", "type": "bytes" }
+  ]
+}"#;
+
+pub const REQUEST_PROTO_SCHEMA: &str = r#"syntax = "proto3";
+package signer.v1;
+
+message SignRequest {
+  string msg_id = 1;
+  bytes message = 2;
+}
+"#;
+
+pub const RESPONSE_PROTO_SCHEMA: &str = r#"syntax = "proto3";
+package signer.v1;
+
+message SignResponse {
+  string msg_id = 1;
+  string resp_id = 2;
+  string key_id = 3;
+  bytes signature = 4;
+}
+"#;
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct SignRequest {
+    #[prost(string, tag = "1")]
+    msg_id: String,
+    #[prost(bytes = "vec", tag = "2")]
+    message: Vec<u8>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct SignResponse {
+    #[prost(string, tag = "1")]
+    msg_id: String,
+    #[prost(string, tag = "2")]
+    resp_id: String,
+    #[prost(string, tag = "3")]
+    key_id: String,
+    #[prost(bytes = "vec", tag = "4")]
+    signature: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PayloadFormat {
+    #[default]
+    Raw,
+    Avro,
+    Protobuf,
+}
+
+impl FromStr for PayloadFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "raw" => Ok(Self::Raw),
+            "avro" => Ok(Self::Avro),
+            "protobuf" => Ok(Self::Protobuf),
+            other => Err(format!(
+                "unknown payload format `{}`, expected one of: raw, avro, protobuf",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for PayloadFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Raw => "raw",
+            Self::Avro => "avro",
+            Self::Protobuf => "protobuf",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SchemaIds {
+    pub request: u32,
+    pub response: u32,
+}
+
+#[derive(Debug)]
+pub struct AvroSchemas {
+    ids: SchemaIds,
+    request: Schema,
+    response: Schema,
+}
+
+/// Encodes and decodes `signer.v1` payloads in one [`PayloadFormat`]
+#[derive(Debug, Clone, Default)]
+pub enum PayloadCodec {
+    #[default]
+    Raw,
+    Avro(Arc<AvroSchemas>),
+    Protobuf(SchemaIds),
+}
+
+impl PayloadCodec {
+    /// Register schemas of `format` in `registry`
+    ///
+    /// `registry` is not used for [`PayloadFormat::Raw`].
+    pub async fn register(
+        format: PayloadFormat,
+        registry: &SchemaRegistry,
+    ) -> Result<Self, RegistryError> {
+        match format {
+            PayloadFormat::Raw => Ok(Self::Raw),
+            PayloadFormat::Avro => {
+                let ids = SchemaIds {
+                    request: registry
+                        .register(REQUEST_SUBJECT, SchemaType::Avro, REQUEST_AVRO_SCHEMA)
+                        .await?,
+                    response: registry
+                        .register(RESPONSE_SUBJECT, SchemaType::Avro, RESPONSE_AVRO_SCHEMA)
+                        .await?,
+                };
+                Ok(Self::Avro(Arc::new(AvroSchemas {
+                    ids,
+                    request: Schema::parse_str(REQUEST_AVRO_SCHEMA).expect("valid avro schema"),
+                    response: Schema::parse_str(RESPONSE_AVRO_SCHEMA).expect("valid avro schema"),
+                })))
+            }
+            PayloadFormat::Protobuf => Ok(Self::Protobuf(SchemaIds {
+                request: registry
+                    .register(REQUEST_SUBJECT, SchemaType::Protobuf, REQUEST_PROTO_SCHEMA)
+                    .await?,
+                response: registry
+                    .register(
+                        RESPONSE_SUBJECT,
+                        SchemaType::Protobuf,
+                        RESPONSE_PROTO_SCHEMA,
+                    )
+                    .await?,
+            })),
+        }
+    }
+
+    pub fn format(&self) -> PayloadFormat {
+        match self {
+            Self::Raw => PayloadFormat::Raw,
+            Self::Avro(_) => PayloadFormat::Avro,
+            Self::Protobuf(_) => PayloadFormat::Protobuf,
+        }
+    }
+
+    /// Kafka payload of `req`, headers are still taken from [`MsgToSign::headers`]
+    pub fn encode_request(&self, req: &MsgToSign) -> Vec<u8> {
+        match self {
+            Self::Raw => req.msg().to_vec(),
+            Self::Avro(schemas) => {
+                let record = Value::Record(vec![
+                    (
+                        "msg_id".to_string(),
+                        Value::String(req.msg_id().to_string()),
+                    ),
+                    ("message".to_string(), Value::Bytes(req.msg().to_vec())),
+                ]);
+                let data = apache_avro::to_avro_datum(&schemas.request, record)
+                    .expect("record matches schema");
+                wire::encode(schemas.ids.request, false, &data)
+            }
+            Self::Protobuf(ids) => {
+                let record = SignRequest {
+                    msg_id: req.msg_id().to_string(),
+                    message: req.msg().to_vec(),
+                };
+                wire::encode(ids.request, true, &prost::Message::encode_to_vec(&record))
+            }
+        }
+    }
+
+    /// Decode request from Kafka message
+    pub fn decode_request<M: Message>(&self, msg: &M) -> Result<MsgToSign, ProtocolError> {
+        let mut raw = MsgToSign::from_message(msg)?;
+        let (msg_id, message) = match self {
+            Self::Raw => return Ok(raw),
+            Self::Avro(schemas) => {
+                let data = avro_data(schemas.ids.request, raw.msg())?;
+                let mut fields = avro_record(&schemas.request, data)?;
+                (
+                    avro_string(&mut fields, "msg_id")?,
+                    avro_bytes(&mut fields, "message")?,
+                )
+            }
+            Self::Protobuf(ids) => {
+                let data = protobuf_data(ids.request, raw.msg())?;
+                let record: SignRequest = prost::Message::decode(data)
+                    .map_err(|err| ProtocolError::MalformedPayload("protobuf", err.to_string()))?;
+                (record.msg_id, record.message)
+            }
+        };
+
+        if msg_id != raw.msg_id() {
+            return Err(ProtocolError::MsgIdMismatch);
+        }
+        raw.msg = message;
+        Ok(raw)
+    }
+
+    /// Kafka payload of `resp`, headers are still taken from [`MsgSigned::headers`]
+    pub fn encode_response(&self, resp: &MsgSigned) -> Vec<u8> {
+        match self {
+            Self::Raw => resp.signed_msg().to_vec(),
+            Self::Avro(schemas) => {
+                let record = Value::Record(vec![
+                    (
+                        "msg_id".to_string(),
+                        Value::String(resp.msg_id().to_string()),
+                    ),
+                    (
+                        "resp_id".to_string(),
+                        Value::String(resp.resp_id().to_string()),
+                    ),
+                    (
+                        "key_id".to_string(),
+                        Value::String(resp.key_id().to_string()),
+                    ),
+                    (
+                        "signature".to_string(),
+                        Value::Bytes(resp.signed_msg().to_vec()),
+                    ),
+                ]);
+                let data = apache_avro::to_avro_datum(&schemas.response, record)
+                    .expect("record matches schema");
+                wire::encode(schemas.ids.response, false, &data)
+            }
+            Self::Protobuf(ids) => {
+                let record = SignResponse {
+                    msg_id: resp.msg_id().to_string(),
+                    resp_id: resp.resp_id().to_string(),
+                    key_id: resp.key_id().to_string(),
+                    signature: resp.signed_msg().to_vec(),
+                };
+                wire::encode(ids.response, true, &prost::Message::encode_to_vec(&record))
+            }
+        }
+    }
+
+    /// Decode signed or failure response from Kafka message
+    ///
+    /// Failure responses have no payload so they look the same in every format.
+    pub fn decode_resp<M: Message>(&self, msg: &M) -> Result<MsgResp, ProtocolError> {
+        if MsgResp::is_failure(msg)? {
+            MsgFailed::from_message(msg).map(MsgResp::Failed)
+        } else {
+            self.decode_response(msg).map(MsgResp::Signed)
+        }
+    }
+
+    /// Decode signed response from Kafka message
+    pub fn decode_response<M: Message>(&self, msg: &M) -> Result<MsgSigned, ProtocolError> {
+        let raw = MsgSigned::from_message(msg)?;
+        let (msg_id, signature) = match self {
+            Self::Raw => return Ok(raw),
+            Self::Avro(schemas) => {
+                let data = avro_data(schemas.ids.response, raw.signed_msg())?;
+                let mut fields = avro_record(&schemas.response, data)?;
+                (
+                    avro_string(&mut fields, "msg_id")?,
+                    avro_bytes(&mut fields, "signature")?,
+                )
+            }
+            Self::Protobuf(ids) => {
+                let data = protobuf_data(ids.response, raw.signed_msg())?;
+                let record: SignResponse = prost::Message::decode(data)
+                    .map_err(|err| ProtocolError::MalformedPayload("protobuf", err.to_string()))?;
+                (record.msg_id, record.signature)
+            }
+        };
+
+        if msg_id != raw.msg_id() {
+            return Err(ProtocolError::MsgIdMismatch);
+        }
+        Ok(MsgSigned::new(
+            msg_id,
+            raw.resp_id().to_string(),
+            raw.key_id().to_string(),
+            signature,
+        )
+        .with_trace_context(raw.trace_context().clone()))
+    }
+}
+
+fn avro_data(expected_id: u32, payload: &[u8]) -> Result<&[u8], ProtocolError> {
+    match wire::decode("avro", false, payload)? {
+        (id, data) if id == expected_id => Ok(data),
+        (id, _) => Err(ProtocolError::UnknownSchemaId(id)),
+    }
+}
+
+fn protobuf_data(expected_id: u32, payload: &[u8]) -> Result<&[u8], ProtocolError> {
+    match wire::decode("protobuf", true, payload)? {
+        (id, data) if id == expected_id => Ok(data),
+        (id, _) => Err(ProtocolError::UnknownSchemaId(id)),
+    }
+}
+
+fn avro_record(schema: &Schema, mut data: &[u8]) -> Result<Vec<(String, Value)>, ProtocolError> {
+    match apache_avro::from_avro_datum(schema, &mut data, None) {
+        Ok(Value::Record(fields)) => Ok(fields),
+        Ok(_) => Err(ProtocolError::MalformedPayload(
+            "avro",
+            "expected record".to_string(),
+        )),
+        Err(err) => Err(ProtocolError::MalformedPayload("avro", err.to_string())),
+    }
+}
+
+fn avro_field(fields: &mut [(String, Value)], name: &str) -> Result<Value, ProtocolError> {
+    fields
+        .iter_mut()
+        .find(|(field, _)| field == name)
+        .map(|(_, value)| std::mem::replace(value, Value::Null))
+        .ok_or_else(|| ProtocolError::MalformedPayload("avro", format!("missing `{}`", name)))
+}
+
+fn avro_string(fields: &mut [(String, Value)], name: &str) -> Result<String, ProtocolError> {
+    match avro_field(fields, name)? {
+        Value::String(value) => Ok(value),
+        _ => Err(ProtocolError::MalformedPayload(
+            "avro",
+            format!("`{}` is not a string", name),
+        )),
+    }
+}
+
+fn avro_bytes(fields: &mut [(String, Value)], name: &str) -> Result<Vec<u8>, ProtocolError> {
+    match avro_field(fields, name)? {
+        Value::Bytes(value) => Ok(value),
+        _ => Err(ProtocolError::MalformedPayload(
+            "avro",
+            format!("`{}` is not bytes", name),
+        )),
+    }
+}
@@ -0,0 +1,57 @@
+//! Kafka headers used by signer protocol
+
+use rdkafka::message::Headers;
+
+use crate::ProtocolError;
+
+pub const MSG_ID: &str = "msg_id";
+pub const RESP_TOPIC: &str = "resp_topic";
+/// Partition of `resp_topic` requester consumes, when topic is shared by many requesters
+pub const RESP_PARTITION: &str = "resp_partition";
+pub const RESP_ID: &str = "resp_id";
+pub const KEY_ID: &str = "key_id";
+/// Unix time in milliseconds after which requester no longer waits for response
+pub const DEADLINE: &str = "deadline";
+/// Present only in failure responses
+pub const ERROR_CODE: &str = "error_code";
+pub const ERROR_MESSAGE: &str = "error_message";
+/// W3C trace context, optional in every message, see [`TraceContext`](crate::TraceContext)
+pub const TRACEPARENT: &str = "traceparent";
+pub const TRACESTATE: &str = "tracestate";
+
+/// Read UTF-8 value of header `key`
+///
+/// Headers can be in any order and headers with other names are ignored, so proxies or tracing
+/// libraries are free to add their own. Header `key` must be present exactly once.
+pub(crate) fn get_str<'a, H>(headers: &'a H, key: &'static str) -> Result<&'a str, ProtocolError>
+where
+    H: Headers + ?Sized,
+{
+    get_opt_str(headers, key)?.ok_or(ProtocolError::MissingHeader(key))
+}
+
+/// Like [`get_str`] but header `key` may be also absent
+pub(crate) fn get_opt_str<'a, H>(
+    headers: &'a H,
+    key: &'static str,
+) -> Result<Option<&'a str>, ProtocolError>
+where
+    H: Headers + ?Sized,
+{
+    let mut found = None;
+    for idx in 0..headers.count() {
+        match headers.get_as::<str>(idx) {
+            Some((name, value)) if name == key => {
+                if found.is_some() {
+                    return Err(ProtocolError::DuplicateHeader(key));
+                }
+                found = Some(value);
+            }
+            _ => (),
+        }
+    }
+
+    found
+        .transpose()
+        .map_err(|_| ProtocolError::InvalidHeaderValue(key))
+}
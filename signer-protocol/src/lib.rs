@@ -0,0 +1,453 @@
+//! Messages exchanged by `signer-rest-api` and `signer-service` through Kafka
+//!
+//! Metadata is send in headers, message to sign and signature in payload:
+//!
+//! | message     | headers                                                            | payload   |
+//! |-------------|--------------------------------------------------------------------|-----------|
+//! | `MsgToSign` | `msg_id`, `resp_topic`, optional `resp_partition` and `deadline`   | message   |
+//! | `MsgSigned` | `msg_id`, `resp_id`, `key_id`                                      | signature |
+//! | `MsgFailed` | `msg_id`, `resp_id`, `error_code`, `error_message`                 | none      |
+//!
+//! Any message can also carry W3C trace context in `traceparent` and `tracestate` headers, see
+//! [`TraceContext`].
+//!
+//! Payload can be also written as Avro or Protobuf record registered in Schema Registry, see
+//! [`format`](mod@format).
+//!
+//! With `telemetry` feature [`telemetry`](mod@telemetry) propagates [`TraceContext`] of
+//! `tracing` spans. With `metrics` feature [`metrics`](mod@metrics) encodes Prometheus metrics of
+//! both applications.
+
+mod error;
+pub mod format;
+pub mod headers;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod schema_registry;
+#[cfg(feature = "telemetry")]
+pub mod telemetry;
+mod trace_context;
+mod wire;
+
+use rdkafka::message::{Headers, OwnedHeaders};
+use rdkafka::Message;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+pub use error::ProtocolError;
+pub use format::{PayloadCodec, PayloadFormat};
+pub use trace_context::TraceContext;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MsgToSign {
+    // headers
+    msg_id: String, //this is general id could be topic+partition_id+offset?
+    resp_topic: String,
+    resp_partition: Option<i32>,
+    deadline: Option<SystemTime>,
+    trace_context: TraceContext,
+    // payload
+    msg: Vec<u8>,
+}
+
+impl MsgToSign {
+    /// Create request with new random `msg_id`
+    pub fn new(msg: Vec<u8>, resp_topic: String) -> Self {
+        Self::with_msg_id(Uuid::new_v4().to_string(), msg, resp_topic)
+    }
+
+    pub fn with_msg_id(msg_id: String, msg: Vec<u8>, resp_topic: String) -> Self {
+        Self {
+            msg_id,
+            resp_topic,
+            resp_partition: None,
+            deadline: None,
+            trace_context: TraceContext::default(),
+            msg,
+        }
+    }
+
+    /// Requester won't wait for response after `deadline`. It's send with millisecond precision.
+    pub fn with_deadline(mut self, deadline: SystemTime) -> Self {
+        self.deadline = Some(from_unix_millis(unix_millis(deadline)));
+        self
+    }
+
+    /// Response must be produced to `resp_partition` of `resp_topic`, which is shared by many
+    /// requesters each consuming own partition
+    pub fn with_resp_partition(mut self, resp_partition: i32) -> Self {
+        self.resp_partition = Some(resp_partition);
+        self
+    }
+
+    /// Trace context of span which sent request
+    pub fn with_trace_context(mut self, trace_context: TraceContext) -> Self {
+        self.trace_context = trace_context;
+        self
+    }
+
+    pub fn msg_id(&self) -> &str {
+        &self.msg_id
+    }
+
+    pub fn resp_topic(&self) -> &str {
+        &self.resp_topic
+    }
+
+    pub fn resp_partition(&self) -> Option<i32> {
+        self.resp_partition
+    }
+
+    pub fn msg(&self) -> &[u8] {
+        &self.msg
+    }
+
+    pub fn deadline(&self) -> Option<SystemTime> {
+        self.deadline
+    }
+
+    pub fn trace_context(&self) -> &TraceContext {
+        &self.trace_context
+    }
+
+    /// Deadline of request passed, so nobody waits for response
+    pub fn is_expired(&self) -> bool {
+        matches!(self.deadline, Some(deadline) if deadline <= SystemTime::now())
+    }
+
+    pub fn headers(&self) -> OwnedHeaders {
+        let mut headers = OwnedHeaders::new_with_capacity(4)
+            .add(headers::MSG_ID, self.msg_id())
+            .add(headers::RESP_TOPIC, self.resp_topic());
+        if let Some(resp_partition) = self.resp_partition {
+            headers = headers.add(headers::RESP_PARTITION, &resp_partition.to_string());
+        }
+
+        if let Some(deadline) = self.deadline {
+            headers = headers.add(headers::DEADLINE, &unix_millis(deadline).to_string());
+        }
+        self.trace_context.add_headers(headers)
+    }
+
+    /// Decode request from Kafka message
+    pub fn from_message<M: Message>(msg: &M) -> Result<Self, ProtocolError> {
+        let hs = msg.headers().ok_or(ProtocolError::MissingHeaders)?;
+        let msg_id = headers::get_str(hs, headers::MSG_ID)?;
+        let resp_topic = headers::get_str(hs, headers::RESP_TOPIC)?;
+        let resp_partition = parse_resp_partition(hs)?;
+        let deadline = headers::get_opt_str(hs, headers::DEADLINE)?
+            .map(|millis| {
+                millis.parse().map(from_unix_millis).map_err(|_| {
+                    ProtocolError::MalformedHeaderValue {
+                        header: headers::DEADLINE,
+                        value: millis.to_string(),
+                    }
+                })
+            })
+            .transpose()?;
+        let payload = msg.payload().ok_or(ProtocolError::MissingPayload)?;
+
+        let req = Self::with_msg_id(msg_id.to_string(), payload.to_vec(), resp_topic.to_string());
+        Ok(Self {
+            resp_partition,
+            deadline,
+            trace_context: TraceContext::from_headers(hs),
+            ..req
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MsgSigned {
+    // headers
+    msg_id: String, //this is general id could be topic+partition_id+offset?
+    resp_id: String,
+    key_id: String,
+    trace_context: TraceContext,
+    // payload
+    signed_msg: Vec<u8>,
+}
+
+impl MsgSigned {
+    pub fn new(
+        req_msg_id: String,
+        resp_msg_id: String,
+        key_id: String,
+        signed_msg: Vec<u8>,
+    ) -> Self {
+        Self {
+            msg_id: req_msg_id,
+            resp_id: resp_msg_id,
+            key_id,
+            trace_context: TraceContext::default(),
+            signed_msg,
+        }
+    }
+
+    /// Trace context of span which signed request
+    pub fn with_trace_context(mut self, trace_context: TraceContext) -> Self {
+        self.trace_context = trace_context;
+        self
+    }
+
+    /// `msg_id` of request this message is response to
+    pub fn msg_id(&self) -> &str {
+        &self.msg_id
+    }
+
+    pub fn resp_id(&self) -> &str {
+        &self.resp_id
+    }
+
+    /// Id of the key used to sign message
+    pub fn key_id(&self) -> &str {
+        &self.key_id
+    }
+
+    /// Detached signature of requested message
+    pub fn signed_msg(&self) -> &[u8] {
+        &self.signed_msg
+    }
+
+    pub fn trace_context(&self) -> &TraceContext {
+        &self.trace_context
+    }
+
+    pub fn headers(&self) -> OwnedHeaders {
+        let headers = OwnedHeaders::new_with_capacity(3)
+            .add(headers::MSG_ID, self.msg_id())
+            .add(headers::RESP_ID, self.resp_id())
+            .add(headers::KEY_ID, self.key_id());
+        self.trace_context.add_headers(headers)
+    }
+
+    /// Decode response from Kafka message
+    pub fn from_message<M: Message>(msg: &M) -> Result<Self, ProtocolError> {
+        let hs = msg.headers().ok_or(ProtocolError::MissingHeaders)?;
+        let msg_id = headers::get_str(hs, headers::MSG_ID)?;
+        let resp_id = headers::get_str(hs, headers::RESP_ID)?;
+        let key_id = headers::get_str(hs, headers::KEY_ID)?;
+        let payload = msg.payload().ok_or(ProtocolError::MissingPayload)?;
+
+        Ok(Self::new(
+            msg_id.to_string(),
+            resp_id.to_string(),
+            key_id.to_string(),
+            payload.to_vec(),
+        )
+        .with_trace_context(TraceContext::from_headers(hs)))
+    }
+}
+
+/// Why request wasn't signed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    /// Deadline of request passed before it was signed
+    Expired,
+    /// Request doesn't follow signer protocol
+    InvalidRequest,
+    /// Signer failed to produce signature
+    SigningFailed,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::Expired => "expired",
+            ErrorCode::InvalidRequest => "invalid_request",
+            ErrorCode::SigningFailed => "signing_failed",
+        }
+    }
+}
+
+impl FromStr for ErrorCode {
+    type Err = ProtocolError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "expired" => Ok(ErrorCode::Expired),
+            "invalid_request" => Ok(ErrorCode::InvalidRequest),
+            "signing_failed" => Ok(ErrorCode::SigningFailed),
+            _ => Err(ProtocolError::MalformedHeaderValue {
+                header: headers::ERROR_CODE,
+                value: s.to_string(),
+            }),
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Response to request that wasn't signed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MsgFailed {
+    // headers
+    msg_id: String,
+    resp_id: String,
+    code: ErrorCode,
+    message: String,
+    trace_context: TraceContext,
+}
+
+impl MsgFailed {
+    pub fn new(req_msg_id: String, resp_msg_id: String, code: ErrorCode, message: String) -> Self {
+        Self {
+            msg_id: req_msg_id,
+            resp_id: resp_msg_id,
+            code,
+            message,
+            trace_context: TraceContext::default(),
+        }
+    }
+
+    /// Trace context of span which handled request
+    pub fn with_trace_context(mut self, trace_context: TraceContext) -> Self {
+        self.trace_context = trace_context;
+        self
+    }
+
+    /// `msg_id` of request this message is response to
+    pub fn msg_id(&self) -> &str {
+        &self.msg_id
+    }
+
+    pub fn resp_id(&self) -> &str {
+        &self.resp_id
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        self.code
+    }
+
+    /// Human readable description of failure
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn trace_context(&self) -> &TraceContext {
+        &self.trace_context
+    }
+
+    pub fn headers(&self) -> OwnedHeaders {
+        let headers = OwnedHeaders::new_with_capacity(4)
+            .add(headers::MSG_ID, self.msg_id())
+            .add(headers::RESP_ID, self.resp_id())
+            .add(headers::ERROR_CODE, self.code.as_str())
+            .add(headers::ERROR_MESSAGE, self.message());
+        self.trace_context.add_headers(headers)
+    }
+
+    /// Decode failure response from Kafka message
+    pub fn from_message<M: Message>(msg: &M) -> Result<Self, ProtocolError> {
+        let hs = msg.headers().ok_or(ProtocolError::MissingHeaders)?;
+        let msg_id = headers::get_str(hs, headers::MSG_ID)?;
+        let resp_id = headers::get_str(hs, headers::RESP_ID)?;
+        let code = headers::get_str(hs, headers::ERROR_CODE)?.parse()?;
+        let message = headers::get_str(hs, headers::ERROR_MESSAGE)?;
+
+        Ok(Self::new(
+            msg_id.to_string(),
+            resp_id.to_string(),
+            code,
+            message.to_string(),
+        )
+        .with_trace_context(TraceContext::from_headers(hs)))
+    }
+}
+
+/// Any response send by signer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MsgResp {
+    Signed(MsgSigned),
+    Failed(MsgFailed),
+}
+
+impl MsgResp {
+    /// `msg_id` of request this message is response to
+    pub fn msg_id(&self) -> &str {
+        match self {
+            MsgResp::Signed(signed) => signed.msg_id(),
+            MsgResp::Failed(failed) => failed.msg_id(),
+        }
+    }
+
+    pub fn trace_context(&self) -> &TraceContext {
+        match self {
+            MsgResp::Signed(signed) => signed.trace_context(),
+            MsgResp::Failed(failed) => failed.trace_context(),
+        }
+    }
+
+    pub fn with_trace_context(self, trace_context: TraceContext) -> Self {
+        match self {
+            MsgResp::Signed(signed) => signed.with_trace_context(trace_context).into(),
+            MsgResp::Failed(failed) => failed.with_trace_context(trace_context).into(),
+        }
+    }
+
+    /// Kafka message is failure response
+    pub fn is_failure<M: Message>(msg: &M) -> Result<bool, ProtocolError> {
+        let hs = msg.headers().ok_or(ProtocolError::MissingHeaders)?;
+        Ok(headers::get_opt_str(hs, headers::ERROR_CODE)?.is_some())
+    }
+}
+
+impl From<MsgSigned> for MsgResp {
+    fn from(signed: MsgSigned) -> Self {
+        MsgResp::Signed(signed)
+    }
+}
+
+impl From<MsgFailed> for MsgResp {
+    fn from(failed: MsgFailed) -> Self {
+        MsgResp::Failed(failed)
+    }
+}
+
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+fn from_unix_millis(millis: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(millis)
+}
+
+/// Read `msg_id` header from message produced as `MsgToSign` or `MsgSigned`
+pub fn msg_id<M: Message>(msg: &M) -> Result<&str, ProtocolError> {
+    let hs = msg.headers().ok_or(ProtocolError::MissingHeaders)?;
+    headers::get_str(hs, headers::MSG_ID)
+}
+
+/// Read `resp_topic` header from message produced as `MsgToSign`
+///
+/// Together with [`msg_id`] it allows to answer request which can't be decoded.
+pub fn resp_topic<M: Message>(msg: &M) -> Result<&str, ProtocolError> {
+    let hs = msg.headers().ok_or(ProtocolError::MissingHeaders)?;
+    headers::get_str(hs, headers::RESP_TOPIC)
+}
+
+/// Read optional `resp_partition` header from message produced as `MsgToSign`
+pub fn resp_partition<M: Message>(msg: &M) -> Result<Option<i32>, ProtocolError> {
+    let hs = msg.headers().ok_or(ProtocolError::MissingHeaders)?;
+    parse_resp_partition(hs)
+}
+
+fn parse_resp_partition<H: Headers + ?Sized>(hs: &H) -> Result<Option<i32>, ProtocolError> {
+    headers::get_opt_str(hs, headers::RESP_PARTITION)?
+        .map(|partition| match partition.parse() {
+            Ok(partition) if partition >= 0 => Ok(partition),
+            _ => Err(ProtocolError::MalformedHeaderValue {
+                header: headers::RESP_PARTITION,
+                value: partition.to_string(),
+            }),
+        })
+        .transpose()
+}
@@ -0,0 +1,40 @@
+//! Prometheus registry of `signer-rest-api` and `signer-service` metrics, served at `/metrics`
+//!
+//! Needs `metrics` feature.
+
+use prometheus::core::Collector;
+use prometheus::{Encoder, Registry, TextEncoder};
+
+/// `Content-Type` of [`MetricsRegistry::encode`] output
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// Registry of metrics of single application instance
+#[derive(Debug, Clone, Default)]
+pub struct MetricsRegistry {
+    registry: Registry,
+}
+
+impl MetricsRegistry {
+    /// Register `metric` and return it back
+    ///
+    /// Panics when metric with the same name is already registered.
+    pub fn register<M: Collector + Clone + 'static>(&self, metric: M) -> M {
+        self.registry
+            .register(Box::new(metric.clone()))
+            .expect("metrics have unique names");
+        metric
+    }
+
+    pub fn registry(&self) -> &Registry {
+        &self.registry
+    }
+
+    /// Metrics in Prometheus text format
+    pub fn encode(&self) -> String {
+        let mut buf = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buf)
+            .expect("text encoding doesn't fail");
+        String::from_utf8(buf).expect("text format is utf8")
+    }
+}
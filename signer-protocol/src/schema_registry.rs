@@ -0,0 +1,94 @@
+//! Minimal client of Confluent Schema Registry
+
+use hyper::client::HttpConnector;
+use hyper::{header, Body, Client, Method, Request, StatusCode};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, thiserror::Error)]
+pub enum RegistryError {
+    #[error("invalid schema registry url `{0}`, expected `http://host:port`")]
+    InvalidUrl(String),
+    #[error("schema registry request failed")]
+    Http(#[from] hyper::Error),
+    #[error("schema registry responded with {status}: {body}")]
+    Status { status: StatusCode, body: String },
+    #[error("unexpected schema registry response")]
+    Json(#[from] serde_json::Error),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum SchemaType {
+    Avro,
+    Protobuf,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RegisterReq<'a> {
+    schema: &'a str,
+    schema_type: SchemaType,
+}
+
+#[derive(Debug, Deserialize)]
+struct RegisterResp {
+    id: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct SchemaRegistry {
+    base_url: String,
+    client: Client<HttpConnector>,
+}
+
+impl SchemaRegistry {
+    /// `url` -- for example `http://schemaregistry.confluent.svc.cluster.local:8081`. Only `http` is supported.
+    pub fn new(url: &str) -> Result<Self, RegistryError> {
+        let base_url = url.trim_end_matches('/');
+        if !base_url.starts_with("http://") || base_url.parse::<hyper::Uri>().is_err() {
+            return Err(RegistryError::InvalidUrl(url.to_string()));
+        }
+
+        Ok(Self {
+            base_url: base_url.to_string(),
+            client: Client::new(),
+        })
+    }
+
+    /// Register `schema` under `subject` and return its global id
+    ///
+    /// Registering already known schema is a no-op that returns the same id.
+    pub async fn register(
+        &self,
+        subject: &str,
+        schema_type: SchemaType,
+        schema: &str,
+    ) -> Result<u32, RegistryError> {
+        let body = serde_json::to_vec(&RegisterReq {
+            schema,
+            schema_type,
+        })?;
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri(format!("{}/subjects/{}/versions", self.base_url, subject))
+            .header(
+                header::CONTENT_TYPE,
+                "application/vnd.schemaregistry.v1+json",
+            )
+            .body(Body::from(body))
+            .map_err(|_| RegistryError::InvalidUrl(self.base_url.clone()))?;
+
+        let resp = self.client.request(req).await?;
+        let status = resp.status();
+        let body = hyper::body::to_bytes(resp.into_body()).await?;
+        if !status.is_success() {
+            return Err(RegistryError::Status {
+                status,
+                body: String::from_utf8_lossy(&body).into_owned(),
+            });
+        }
+
+        let RegisterResp { id } = serde_json::from_slice(&body)?;
+        Ok(id)
+    }
+}
@@ -0,0 +1,113 @@
+//! Logs and OpenTelemetry traces of `signer-rest-api` and `signer-service`
+//!
+//! Trace context of sign request span is sent to `signer-service` in Kafka headers, its span of
+//! the request is child of it, and its own trace context comes back with response, so the whole
+//! round trip is a single trace. Spans are exported with OTLP when `OTEL_EXPORTER_OTLP_ENDPOINT`
+//! (or `OTEL_EXPORTER_OTLP_TRACES_ENDPOINT`) is set.
+//!
+//! Needs `telemetry` feature.
+
+use std::env;
+
+use opentelemetry::propagation::{Extractor, Injector, TextMapPropagator};
+use opentelemetry::sdk::propagation::TraceContextPropagator;
+use opentelemetry::sdk::{trace, Resource};
+use opentelemetry::trace::{TraceError, TracerProvider as _};
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::{
+    SpanExporterBuilder, WithExportConfig, OTEL_EXPORTER_OTLP_ENDPOINT,
+    OTEL_EXPORTER_OTLP_TRACES_ENDPOINT,
+};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::filter::EnvFilter;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::util::TryInitError;
+
+use crate::{headers, TraceContext};
+
+#[derive(Debug, thiserror::Error)]
+pub enum TelemetryError {
+    #[error("failed to build OTLP exporter: {0}")]
+    Exporter(#[from] TraceError),
+    #[error("failed to install subscriber: {0}")]
+    Subscriber(#[from] TryInitError),
+}
+
+/// Install global subscriber logging to stdout and recording spans for OpenTelemetry
+///
+/// `service_name` is `service.name` of exported spans unless `OTEL_SERVICE_NAME` is set. Without
+/// OTLP endpoint spans aren't exported, but still have ids passed to the other side. Log level is
+/// read from `RUST_LOG`, `info` if it's not set.
+pub fn init(service_name: &'static str) -> Result<(), TelemetryError> {
+    let resource_name = env::var("OTEL_SERVICE_NAME").unwrap_or_else(|_| service_name.to_string());
+    let config = trace::config().with_resource(Resource::new([KeyValue::new(
+        "service.name",
+        resource_name,
+    )]));
+    let mut provider = trace::TracerProvider::builder().with_config(config);
+    let otlp_endpoint = env::var(OTEL_EXPORTER_OTLP_TRACES_ENDPOINT)
+        .or_else(|_| env::var(OTEL_EXPORTER_OTLP_ENDPOINT))
+        .ok();
+    if otlp_endpoint.is_some() {
+        let exporter =
+            SpanExporterBuilder::from(opentelemetry_otlp::new_exporter().tonic().with_env())
+                .build_span_exporter()?;
+        provider = provider.with_batch_exporter(exporter, opentelemetry::runtime::Tokio);
+    }
+    let provider = provider.build();
+    let tracer = provider.tracer(service_name);
+    // keeps provider alive, see `shutdown`
+    opentelemetry::global::set_tracer_provider(provider);
+
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer())
+        .with(tracing_opentelemetry::layer().with_tracer(tracer))
+        .try_init()?;
+
+    if let Some(endpoint) = otlp_endpoint {
+        tracing::info!("exporting spans to {}", endpoint);
+    }
+    Ok(())
+}
+
+/// Export spans which weren't exported yet
+pub fn shutdown() {
+    opentelemetry::global::shutdown_tracer_provider();
+}
+
+/// Trace context of `span` to be sent in Kafka headers
+pub fn inject(span: &tracing::Span) -> TraceContext {
+    let mut trace_context = TraceContext::default();
+    TraceContextPropagator::new().inject_context(&span.context(), &mut Carrier(&mut trace_context));
+    trace_context
+}
+
+/// Make `span` child of span which sent message with `trace_context`, no-op if it's empty
+pub fn set_parent(span: &tracing::Span, trace_context: &TraceContext) {
+    if !trace_context.is_empty() {
+        span.set_parent(TraceContextPropagator::new().extract(&Carrier(trace_context)));
+    }
+}
+
+struct Carrier<T>(T);
+
+impl Injector for Carrier<&mut TraceContext> {
+    fn set(&mut self, key: &str, value: String) {
+        self.0.set(key, value);
+    }
+}
+
+impl Extractor for Carrier<&TraceContext> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key)
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        [headers::TRACEPARENT, headers::TRACESTATE]
+            .into_iter()
+            .filter(|key| self.0.get(key).is_some())
+            .collect()
+    }
+}
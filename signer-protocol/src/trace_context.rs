@@ -0,0 +1,61 @@
+use rdkafka::message::{Headers, OwnedHeaders};
+
+use crate::headers;
+
+/// W3C trace context of span which produced message, so signer and requester spans end up in
+/// single trace
+///
+/// Values are passed as is, it's up to tracing library to parse them. Tracing is best effort, so
+/// malformed or duplicated headers are ignored instead of failing the message.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TraceContext {
+    pub traceparent: Option<String>,
+    pub tracestate: Option<String>,
+}
+
+impl TraceContext {
+    pub fn is_empty(&self) -> bool {
+        self.traceparent.is_none() && self.tracestate.is_none()
+    }
+
+    /// Value of header `key`, `traceparent` or `tracestate`
+    pub fn get(&self, key: &str) -> Option<&str> {
+        match key {
+            headers::TRACEPARENT => self.traceparent.as_deref(),
+            headers::TRACESTATE => self.tracestate.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Set header `key`, other than `traceparent` and `tracestate` are ignored
+    pub fn set(&mut self, key: &str, value: String) {
+        match key {
+            headers::TRACEPARENT => self.traceparent = Some(value),
+            headers::TRACESTATE => self.tracestate = Some(value),
+            _ => (),
+        }
+    }
+
+    pub(crate) fn add_headers(&self, mut hs: OwnedHeaders) -> OwnedHeaders {
+        if let Some(traceparent) = &self.traceparent {
+            hs = hs.add(headers::TRACEPARENT, traceparent);
+        }
+        if let Some(tracestate) = &self.tracestate {
+            hs = hs.add(headers::TRACESTATE, tracestate);
+        }
+        hs
+    }
+
+    pub(crate) fn from_headers<H: Headers + ?Sized>(hs: &H) -> Self {
+        let get = |key| {
+            headers::get_opt_str(hs, key)
+                .ok()
+                .flatten()
+                .map(str::to_string)
+        };
+        Self {
+            traceparent: get(headers::TRACEPARENT),
+            tracestate: get(headers::TRACESTATE),
+        }
+    }
+}
@@ -0,0 +1,63 @@
+//! Confluent wire format: magic byte, big endian schema id, (protobuf message indexes) and data
+
+use crate::ProtocolError;
+
+const MAGIC_BYTE: u8 = 0;
+
+/// Prefix `data` with header pointing to `schema_id`
+///
+/// With `protobuf` the message indexes are written too. We always use the first message in schema
+/// and it's encoded as single `0` byte.
+pub(crate) fn encode(schema_id: u32, protobuf: bool, data: &[u8]) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(6 + data.len());
+    payload.push(MAGIC_BYTE);
+    payload.extend_from_slice(&schema_id.to_be_bytes());
+    if protobuf {
+        payload.push(0);
+    }
+    payload.extend_from_slice(data);
+    payload
+}
+
+/// Split `payload` into schema id and data
+pub(crate) fn decode<'a>(
+    format: &'static str,
+    protobuf: bool,
+    payload: &'a [u8],
+) -> Result<(u32, &'a [u8]), ProtocolError> {
+    let malformed = |reason: &str| ProtocolError::MalformedPayload(format, reason.to_string());
+
+    match payload {
+        [MAGIC_BYTE, a, b, c, d, rest @ ..] => {
+            let schema_id = u32::from_be_bytes([*a, *b, *c, *d]);
+            if !protobuf {
+                return Ok((schema_id, rest));
+            }
+
+            let mut buf = rest;
+            let count = zigzag(prost::encoding::decode_varint(&mut buf))
+                .map_err(|()| malformed("invalid message indexes"))?;
+            if count < 0 {
+                return Err(malformed("invalid message indexes"));
+            }
+            // `0` is shortcut for `[0]`
+            if count != 0 {
+                for _ in 0..count {
+                    let index = zigzag(prost::encoding::decode_varint(&mut buf))
+                        .map_err(|()| malformed("invalid message indexes"))?;
+                    if index != 0 {
+                        return Err(malformed("expected first message of schema"));
+                    }
+                }
+            }
+            Ok((schema_id, buf))
+        }
+        [MAGIC_BYTE, ..] => Err(malformed("payload is too short")),
+        _ => Err(malformed("unknown magic byte")),
+    }
+}
+
+fn zigzag(varint: Result<u64, prost::DecodeError>) -> Result<i64, ()> {
+    let n = varint.map_err(drop)?;
+    Ok((n >> 1) as i64 ^ -((n & 1) as i64))
+}
@@ -0,0 +1,217 @@
+use rdkafka::message::{OwnedHeaders, OwnedMessage, Timestamp};
+use signer_protocol::format::SchemaIds;
+use signer_protocol::{MsgSigned, MsgToSign, PayloadCodec, ProtocolError, TraceContext};
+
+fn kafka_msg(headers: Option<OwnedHeaders>, payload: Option<&[u8]>) -> OwnedMessage {
+    OwnedMessage::new(
+        payload.map(<[u8]>::to_vec),
+        None,
+        "signer.v1".to_string(),
+        Timestamp::NotAvailable,
+        0,
+        0,
+        headers,
+    )
+}
+
+#[test]
+fn headers_in_any_order() {
+    let headers = OwnedHeaders::new()
+        .add("resp_topic", "signer.v1.resp0")
+        .add("msg_id", "1");
+    let req = MsgToSign::from_message(&kafka_msg(Some(headers), Some(b"hello"))).unwrap();
+
+    assert_eq!(req.msg_id(), "1");
+    assert_eq!(req.resp_topic(), "signer.v1.resp0");
+    assert_eq!(req.msg(), b"hello");
+}
+
+#[test]
+fn unknown_headers_are_ignored() {
+    let headers = OwnedHeaders::new()
+        .add(
+            "uber-trace-id",
+            "0af7651916cd43dd8448eb211c80319c:b7ad6b7169203331:0:1",
+        )
+        .add("key_id", "signer-key-1")
+        .add("x-proxy", &[0xff, 0xfe][..])
+        .add("resp_id", "2")
+        .add("msg_id", "1");
+    let resp = MsgSigned::from_message(&kafka_msg(Some(headers), Some(b"sig"))).unwrap();
+
+    assert_eq!(
+        resp,
+        MsgSigned::new(
+            "1".to_string(),
+            "2".to_string(),
+            "signer-key-1".to_string(),
+            b"sig".to_vec()
+        )
+    );
+}
+
+#[test]
+fn duplicated_header_is_rejected() {
+    let headers = OwnedHeaders::new()
+        .add("msg_id", "1")
+        .add("resp_topic", "signer.v1.resp0")
+        .add("msg_id", "2");
+    let err = MsgToSign::from_message(&kafka_msg(Some(headers), Some(b"hello"))).unwrap_err();
+
+    assert_eq!(err, ProtocolError::DuplicateHeader("msg_id"));
+}
+
+#[test]
+fn missing_header_is_rejected() {
+    let headers = OwnedHeaders::new().add("msg_id", "1").add("resp_id", "2");
+    let err = MsgSigned::from_message(&kafka_msg(Some(headers), Some(b"sig"))).unwrap_err();
+
+    assert_eq!(err, ProtocolError::MissingHeader("key_id"));
+}
+
+#[test]
+fn non_utf8_header_value_is_rejected() {
+    let headers = OwnedHeaders::new()
+        .add("msg_id", &[0xff][..])
+        .add("resp_topic", "signer.v1.resp0");
+    let err = MsgToSign::from_message(&kafka_msg(Some(headers), Some(b"hello"))).unwrap_err();
+
+    assert_eq!(err, ProtocolError::InvalidHeaderValue("msg_id"));
+}
+
+#[test]
+fn missing_headers_and_payload_are_rejected() {
+    let err = MsgToSign::from_message(&kafka_msg(None, Some(b"hello"))).unwrap_err();
+    assert_eq!(err, ProtocolError::MissingHeaders);
+
+    let headers = OwnedHeaders::new()
+        .add("msg_id", "1")
+        .add("resp_topic", "signer.v1.resp0");
+    let err = MsgToSign::from_message(&kafka_msg(Some(headers), None)).unwrap_err();
+    assert_eq!(err, ProtocolError::MissingPayload);
+}
+
+#[test]
+fn invalid_deadline_is_rejected() {
+    let headers = OwnedHeaders::new()
+        .add("msg_id", "1")
+        .add("resp_topic", "signer.v1.resp0")
+        .add("deadline", "tomorrow");
+    let err = MsgToSign::from_message(&kafka_msg(Some(headers), Some(b"hello"))).unwrap_err();
+
+    assert_eq!(
+        err,
+        ProtocolError::MalformedHeaderValue {
+            header: "deadline",
+            value: "tomorrow".to_string(),
+        }
+    );
+    assert_eq!(
+        err.to_string(),
+        "malformed value `tomorrow` of `deadline` header"
+    );
+}
+
+#[test]
+fn invalid_resp_partition_is_rejected() {
+    for partition in ["first", "-1"] {
+        let headers = OwnedHeaders::new()
+            .add("msg_id", "1")
+            .add("resp_topic", "signer.v1.resp")
+            .add("resp_partition", partition);
+        let msg = kafka_msg(Some(headers), Some(b"hello"));
+
+        let err = MsgToSign::from_message(&msg).unwrap_err();
+        assert_eq!(
+            err,
+            ProtocolError::MalformedHeaderValue {
+                header: "resp_partition",
+                value: partition.to_string(),
+            }
+        );
+        assert_eq!(signer_protocol::resp_partition(&msg), Err(err));
+    }
+}
+
+#[test]
+fn request_without_deadline_never_expires() {
+    let headers = OwnedHeaders::new()
+        .add("msg_id", "1")
+        .add("resp_topic", "signer.v1.resp0");
+    let req = MsgToSign::from_message(&kafka_msg(Some(headers), Some(b"hello"))).unwrap();
+    assert_eq!(req.deadline(), None);
+    assert!(!req.is_expired());
+
+    let req = req.with_deadline(std::time::SystemTime::now() - std::time::Duration::from_secs(1));
+    assert!(req.is_expired());
+}
+
+#[test]
+fn trace_context_is_read_from_headers() {
+    let traceparent = "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01";
+    let headers = OwnedHeaders::new()
+        .add("tracestate", "vendor=value")
+        .add("msg_id", "1")
+        .add("resp_id", "2")
+        .add("traceparent", traceparent)
+        .add("error_code", "expired")
+        .add("error_message", "too late");
+    let resp = PayloadCodec::Raw
+        .decode_resp(&kafka_msg(Some(headers), None))
+        .unwrap();
+
+    assert_eq!(
+        resp.trace_context(),
+        &TraceContext {
+            traceparent: Some(traceparent.to_string()),
+            tracestate: Some("vendor=value".to_string()),
+        }
+    );
+}
+
+#[test]
+fn malformed_trace_context_is_ignored() {
+    let headers = OwnedHeaders::new()
+        .add("msg_id", "1")
+        .add("resp_topic", "signer.v1.resp0")
+        .add("traceparent", &[0xff][..])
+        .add("tracestate", "a=1")
+        .add("tracestate", "b=2");
+    let req = MsgToSign::from_message(&kafka_msg(Some(headers), Some(b"hello"))).unwrap();
+
+    assert_eq!(req.msg_id(), "1");
+    assert!(req.trace_context().is_empty());
+}
+
+#[test]
+fn unknown_error_code_is_rejected() {
+    let err = "too_late"
+        .parse::<signer_protocol::ErrorCode>()
+        .unwrap_err();
+    assert_eq!(
+        err,
+        ProtocolError::MalformedHeaderValue {
+            header: "error_code",
+            value: "too_late".to_string(),
+        }
+    );
+}
+
+#[test]
+fn negative_count_of_message_indexes_is_rejected() {
+    let codec = PayloadCodec::Protobuf(SchemaIds {
+        request: 1,
+        response: 2,
+    });
+    let req = MsgToSign::new(b"hello".to_vec(), "signer.v1.resp0".to_string());
+    // magic byte, schema id 1 and zigzag encoded `-1` count
+    let payload = [0, 0, 0, 0, 1, 1];
+
+    let err = codec
+        .decode_request(&kafka_msg(Some(req.headers()), Some(&payload)))
+        .unwrap_err();
+    assert_eq!(
+        err,
+        ProtocolError::MalformedPayload("protobuf", "invalid message indexes".to_string())
+    );
+}
@@ -0,0 +1,81 @@
+use proptest::prelude::*;
+use rdkafka::message::{OwnedHeaders, OwnedMessage, Timestamp};
+use signer_protocol::{
+    ErrorCode, MsgFailed, MsgResp, MsgSigned, MsgToSign, PayloadCodec, TraceContext,
+};
+use std::time::{Duration, UNIX_EPOCH};
+
+fn kafka_msg(headers: OwnedHeaders, payload: &[u8]) -> OwnedMessage {
+    OwnedMessage::new(
+        Some(payload.to_vec()),
+        None,
+        "signer.v1".to_string(),
+        Timestamp::NotAvailable,
+        0,
+        0,
+        Some(headers),
+    )
+}
+
+proptest! {
+    #[test]
+    fn msg_to_sign_round_trip(
+        msg_id in ".*",
+        resp_topic in "[a-zA-Z0-9._-]{1,249}",
+        msg in proptest::collection::vec(any::<u8>(), 0..1024),
+        resp_partition in proptest::option::of(0..i32::MAX),
+        deadline_millis in proptest::option::of(any::<u32>()),
+        traceparent in proptest::option::of("[0-9a-f-]{55}"),
+        tracestate in proptest::option::of("[a-z0-9=,]*"),
+    ) {
+        let mut req = MsgToSign::with_msg_id(msg_id, msg, resp_topic);
+        if let Some(partition) = resp_partition {
+            req = req.with_resp_partition(partition);
+        }
+        if let Some(millis) = deadline_millis {
+            req = req.with_deadline(UNIX_EPOCH + Duration::from_millis(millis.into()));
+        }
+        req = req.with_trace_context(TraceContext { traceparent, tracestate });
+        let decoded = MsgToSign::from_message(&kafka_msg(req.headers(), req.msg())).unwrap();
+        prop_assert_eq!(decoded, req);
+    }
+
+    #[test]
+    fn msg_signed_round_trip(
+        msg_id in ".*",
+        resp_id in ".*",
+        key_id in ".*",
+        signature in proptest::collection::vec(any::<u8>(), 0..128),
+    ) {
+        let resp = MsgSigned::new(msg_id, resp_id, key_id, signature);
+        let raw = kafka_msg(resp.headers(), resp.signed_msg());
+        prop_assert_eq!(signer_protocol::msg_id(&raw), Ok(resp.msg_id()));
+        let decoded = MsgSigned::from_message(&raw).unwrap();
+        prop_assert_eq!(decoded, resp);
+    }
+
+    #[test]
+    fn msg_failed_round_trip(
+        msg_id in ".*",
+        resp_id in ".*",
+        message in ".*",
+        code in prop_oneof![
+            Just(ErrorCode::Expired),
+            Just(ErrorCode::InvalidRequest),
+            Just(ErrorCode::SigningFailed),
+        ],
+    ) {
+        let resp = MsgFailed::new(msg_id, resp_id, code, message);
+        let raw = OwnedMessage::new(
+            None,
+            None,
+            "signer.v1.resp0".to_string(),
+            Timestamp::NotAvailable,
+            0,
+            0,
+            Some(resp.headers()),
+        );
+        let decoded = PayloadCodec::Raw.decode_resp(&raw).unwrap();
+        prop_assert_eq!(decoded, MsgResp::Failed(resp));
+    }
+}
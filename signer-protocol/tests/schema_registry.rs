@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use rdkafka::message::{OwnedHeaders, OwnedMessage, Timestamp};
+use signer_protocol::schema_registry::SchemaRegistry;
+use signer_protocol::{MsgSigned, MsgToSign, PayloadCodec, PayloadFormat, ProtocolError};
+
+/// Schemas by `(subject, schema)`, ids are assigned in registration order
+type Registered = Arc<Mutex<HashMap<(String, String), u32>>>;
+
+async fn handle(req: Request<Body>, registered: Registered) -> Result<Response<Body>, Infallible> {
+    let subject = req
+        .uri()
+        .path()
+        .strip_prefix("/subjects/")
+        .and_then(|rest| rest.strip_suffix("/versions"))
+        .map(str::to_string);
+    let subject = match (req.method(), subject) {
+        (&Method::POST, Some(subject)) => subject,
+        _ => {
+            let mut resp = Response::new(Body::empty());
+            *resp.status_mut() = StatusCode::NOT_FOUND;
+            return Ok(resp);
+        }
+    };
+
+    let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    let schema = body["schema"].as_str().unwrap().to_string();
+    assert!(matches!(
+        body["schemaType"].as_str(),
+        Some("AVRO" | "PROTOBUF")
+    ));
+
+    let mut registered = registered.lock().unwrap();
+    let next_id = registered.len() as u32 + 1;
+    let id = *registered.entry((subject, schema)).or_insert(next_id);
+    Ok(Response::new(Body::from(format!(r#"{{"id":{}}}"#, id))))
+}
+
+fn mock_registry() -> (SchemaRegistry, Registered) {
+    let registered = Registered::default();
+    let make_svc = make_service_fn({
+        let registered = registered.clone();
+        move |_conn| {
+            let registered = registered.clone();
+            async move { Ok::<_, Infallible>(service_fn(move |req| handle(req, registered.clone()))) }
+        }
+    });
+    let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(make_svc);
+    let url = format!("http://{}", server.local_addr());
+    tokio::spawn(server);
+
+    (SchemaRegistry::new(&url).unwrap(), registered)
+}
+
+fn kafka_msg(headers: OwnedHeaders, payload: Vec<u8>) -> OwnedMessage {
+    OwnedMessage::new(
+        Some(payload),
+        None,
+        "signer.v1".to_string(),
+        Timestamp::NotAvailable,
+        0,
+        0,
+        Some(headers),
+    )
+}
+
+async fn round_trip(format: PayloadFormat) {
+    let (registry, registered) = mock_registry();
+    let codec = PayloadCodec::register(format, &registry).await.unwrap();
+    assert_eq!(codec.format(), format);
+    assert_eq!(registered.lock().unwrap().len(), 2);
+
+    let req = MsgToSign::new(vec![0, 159, 146, 150], "signer.v1.resp0".to_string());
+    let payload = codec.encode_request(&req);
+    assert_eq!(payload[0], 0, "magic byte");
+    let decoded = codec
+        .decode_request(&kafka_msg(req.headers(), payload))
+        .unwrap();
+    assert_eq!(decoded, req);
+
+    let resp = MsgSigned::new(
+        req.msg_id().to_string(),
+        "resp-1".to_string(),
+        "signer-key-1".to_string(),
+        vec![1; 64],
+    );
+    let payload = codec.encode_response(&resp);
+    let decoded = codec
+        .decode_response(&kafka_msg(resp.headers(), payload))
+        .unwrap();
+    assert_eq!(decoded, resp);
+
+    // registering again (e.g. by another instance) yields the same schema ids
+    let again = PayloadCodec::register(format, &registry).await.unwrap();
+    assert_eq!(again.encode_request(&req), codec.encode_request(&req));
+    assert_eq!(registered.lock().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn avro_round_trip() {
+    round_trip(PayloadFormat::Avro).await;
+}
+
+#[tokio::test]
+async fn protobuf_round_trip() {
+    round_trip(PayloadFormat::Protobuf).await;
+}
+
+#[tokio::test]
+async fn raw_payload_is_rejected_by_record_format() {
+    let (registry, _) = mock_registry();
+    let codec = PayloadCodec::register(PayloadFormat::Avro, &registry)
+        .await
+        .unwrap();
+
+    let req = MsgToSign::new(b"hello".to_vec(), "signer.v1.resp0".to_string());
+    let err = codec
+        .decode_request(&kafka_msg(req.headers(), req.msg().to_vec()))
+        .unwrap_err();
+    assert!(matches!(err, ProtocolError::MalformedPayload("avro", _)));
+}
+
+#[tokio::test]
+async fn schema_id_and_msg_id_are_checked() {
+    let (registry, _) = mock_registry();
+    let codec = PayloadCodec::register(PayloadFormat::Protobuf, &registry)
+        .await
+        .unwrap();
+
+    let req = MsgToSign::new(b"hello".to_vec(), "signer.v1.resp0".to_string());
+    let resp = MsgSigned::new(
+        req.msg_id().to_string(),
+        "resp-1".to_string(),
+        "signer-key-1".to_string(),
+        b"sig".to_vec(),
+    );
+
+    // response record sent as request
+    let err = codec
+        .decode_request(&kafka_msg(req.headers(), codec.encode_response(&resp)))
+        .unwrap_err();
+    assert!(matches!(err, ProtocolError::UnknownSchemaId(_)));
+
+    // headers of another request
+    let other = MsgToSign::new(b"hello".to_vec(), "signer.v1.resp0".to_string());
+    let err = codec
+        .decode_request(&kafka_msg(other.headers(), codec.encode_request(&req)))
+        .unwrap_err();
+    assert_eq!(err, ProtocolError::MsgIdMismatch);
+}
@@ -0,0 +1,56 @@
+//! REST API and signer running in one process without Kafka
+//!
+//! ```sh
+//! openssl genpkey -algorithm ed25519 -out signer-key-1.pem
+//! mkdir keys && openssl pkey -in signer-key-1.pem -pubout -out keys/signer-key-1.pem
+//! SIGNER_SERVICE_KEY_FILE=signer-key-1.pem SIGNER_REST_API_PUBLIC_KEYS_DIR=keys \
+//!     cargo run -p signer-rest-api --example in_process
+//! ```
+//! and open `http://127.0.0.1:8080/sign`.
+
+use signer_rest_api::rest::RouterConfig;
+use signer_rest_api::verify::PublicKeyCache;
+use signer_rest_api::Worker;
+use std::env;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::init();
+
+    let key_file = env::var("SIGNER_SERVICE_KEY_FILE")?; //This is required
+    let key_algorithm = env::var("SIGNER_SERVICE_KEY_ALGORITHM")
+        .unwrap_or_else(|_| "ed25519".to_string())
+        .parse()?;
+    let public_keys = match env::var("SIGNER_REST_API_PUBLIC_KEYS_DIR") {
+        Ok(dir) => PublicKeyCache::from_dir(dir)?,
+        Err(_) => PublicKeyCache::default(),
+    };
+
+    let signer = signer_service::signer::from_key_file(&key_file, key_algorithm, None)?;
+    tracing::info!("signing with key `{}`", signer.key_id());
+
+    let (req_tx, req_rx) = mpsc::channel(1024);
+    let (resp_tx, resp_rx) = mpsc::channel(1024);
+
+    let transport = signer_service::transport::InProcessTransport::new(req_rx, resp_tx);
+    let service = signer_service::Service::new(signer);
+    tokio::spawn(async move { service.run(transport).await });
+
+    let transport =
+        signer_rest_api::transport::InProcessTransport::new("in-process", req_tx, resp_rx);
+    let (sign_reqester, _supervisor) = Worker::spawn(transport);
+
+    let router = signer_rest_api::rest::router(
+        sign_reqester,
+        Arc::new(public_keys),
+        RouterConfig::default(),
+    );
+
+    axum::Server::bind(&"127.0.0.1:8080".parse().unwrap())
+        .serve(router.into_make_service())
+        .await?;
+
+    Ok(())
+}
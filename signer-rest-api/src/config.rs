@@ -0,0 +1,186 @@
+//! Kafka clients configuration
+//!
+//! Defaults are overridden by properties file (`SIGNER_REST_API_KAFKA_CONFIG_FILE`) which is
+//! overridden by `SIGNER_REST_API_KAFKA_*` env variables, see [`KafkaConfig::from_env`].
+
+use rdkafka::config::RDKafkaLogLevel;
+use rdkafka::ClientConfig;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::{env, fs, io};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("can't read `{0}`: {1}")]
+    Io(PathBuf, #[source] io::Error),
+    #[error("`{0}` line {1}: expected `key=value`")]
+    InvalidLine(PathBuf, usize),
+    #[error("invalid value of `{0}`: `{1}`")]
+    InvalidValue(String, String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KafkaConfig {
+    pub brokers: String,
+    /// Group id of response consumer. Derived from response topic when `None`, so every instance
+    /// is alone in its group.
+    pub group_id: Option<String>,
+    pub session_timeout_ms: u32,
+    /// Where response consumer starts when group has no committed offset
+    pub auto_offset_reset: String,
+    pub message_timeout_ms: u32,
+    /// Other properties of every client, e.g. `security.protocol`
+    pub common: BTreeMap<String, String>,
+    /// Other properties of response consumer, override `common` ones
+    pub consumer: BTreeMap<String, String>,
+    /// Other properties of request producer, override `common` ones
+    pub producer: BTreeMap<String, String>,
+}
+
+impl Default for KafkaConfig {
+    fn default() -> Self {
+        Self {
+            brokers: "127.0.0.1:9092".to_string(),
+            group_id: None,
+            session_timeout_ms: 6000,
+            auto_offset_reset: "latest".to_string(),
+            message_timeout_ms: 5000,
+            common: BTreeMap::new(),
+            consumer: BTreeMap::new(),
+            producer: BTreeMap::new(),
+        }
+    }
+}
+
+impl KafkaConfig {
+    /// Defaults overridden by `SIGNER_REST_API_KAFKA_CONFIG_FILE` and then by
+    /// `SIGNER_REST_API_KAFKA_BROKERS`, `_GROUP_ID`, `_SESSION_TIMEOUT_MS`, `_AUTO_OFFSET_RESET`
+    /// and `_MESSAGE_TIMEOUT_MS`
+    pub fn from_env() -> Result<Self, ConfigError> {
+        let mut config = Self::default();
+        if let Ok(path) = env::var("SIGNER_REST_API_KAFKA_CONFIG_FILE") {
+            config = config.with_properties_file(path)?;
+        }
+
+        for (var, key) in [
+            ("SIGNER_REST_API_KAFKA_BROKERS", "bootstrap.servers"),
+            ("SIGNER_REST_API_KAFKA_GROUP_ID", "group.id"),
+            (
+                "SIGNER_REST_API_KAFKA_SESSION_TIMEOUT_MS",
+                "session.timeout.ms",
+            ),
+            (
+                "SIGNER_REST_API_KAFKA_AUTO_OFFSET_RESET",
+                "auto.offset.reset",
+            ),
+            (
+                "SIGNER_REST_API_KAFKA_MESSAGE_TIMEOUT_MS",
+                "message.timeout.ms",
+            ),
+        ] {
+            if let Ok(value) = env::var(var) {
+                config.set_typed(key, &value)?;
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Override config with `key=value` lines of file at `path`
+    ///
+    /// Keys prefixed with `consumer.` or `producer.` are set only for that client and override
+    /// everything else, other keys are set for every client. Empty lines and lines starting with
+    /// `#` are skipped.
+    pub fn with_properties_file(mut self, path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let content =
+            fs::read_to_string(path).map_err(|err| ConfigError::Io(path.to_path_buf(), err))?;
+
+        for (idx, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| ConfigError::InvalidLine(path.to_path_buf(), idx + 1))?;
+            self.set(key.trim(), value.trim())?;
+        }
+
+        Ok(self)
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), ConfigError> {
+        if let Some(key) = key.strip_prefix("consumer.") {
+            self.consumer.insert(key.to_string(), value.to_string());
+        } else if let Some(key) = key.strip_prefix("producer.") {
+            self.producer.insert(key.to_string(), value.to_string());
+        } else if !self.set_typed(key, value)? {
+            self.common.insert(key.to_string(), value.to_string());
+        }
+        Ok(())
+    }
+
+    /// Set field of typed property `key`. Returns `false` if there is no such field.
+    fn set_typed(&mut self, key: &str, value: &str) -> Result<bool, ConfigError> {
+        let invalid = || ConfigError::InvalidValue(key.to_string(), value.to_string());
+        match key {
+            "bootstrap.servers" => self.brokers = value.to_string(),
+            "group.id" => self.group_id = Some(value.to_string()),
+            "session.timeout.ms" => {
+                self.session_timeout_ms = value.parse().map_err(|_| invalid())?
+            }
+            "auto.offset.reset" => self.auto_offset_reset = value.to_string(),
+            "message.timeout.ms" => {
+                self.message_timeout_ms = value.parse().map_err(|_| invalid())?
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// Group id of consumer of `resp_topic` (and its `resp_partition`)
+    pub fn group_id(&self, resp_topic: &str, resp_partition: Option<i32>) -> String {
+        match (&self.group_id, resp_partition) {
+            (Some(group_id), _) => group_id.clone(),
+            (None, None) => format!("signer-rest-api.{}", resp_topic),
+            (None, Some(partition)) => format!("signer-rest-api.{}.{}", resp_topic, partition),
+        }
+    }
+
+    /// Config of clients not covered by other methods, like admin client
+    pub fn client_config(&self) -> ClientConfig {
+        let mut config = ClientConfig::new();
+        config.set("bootstrap.servers", &self.brokers);
+        for (key, value) in &self.common {
+            config.set(key, value);
+        }
+        config
+    }
+
+    pub fn producer_config(&self) -> ClientConfig {
+        let mut config = self.client_config();
+        config.set("message.timeout.ms", self.message_timeout_ms.to_string());
+        for (key, value) in &self.producer {
+            config.set(key, value);
+        }
+        config
+    }
+
+    /// Config of consumer of `resp_topic` (and its `resp_partition`)
+    pub fn consumer_config(&self, resp_topic: &str, resp_partition: Option<i32>) -> ClientConfig {
+        let mut config = self.client_config();
+        config
+            .set("group.id", self.group_id(resp_topic, resp_partition))
+            .set("session.timeout.ms", self.session_timeout_ms.to_string())
+            .set("auto.offset.reset", &self.auto_offset_reset)
+            .set("enable.partition.eof", "false")
+            // responses of aborted signer-service transactions are never seen
+            .set("isolation.level", "read_committed")
+            .set_log_level(RDKafkaLogLevel::Debug);
+        for (key, value) in &self.consumer {
+            config.set(key, value);
+        }
+        config
+    }
+}
@@ -1,5 +1,15 @@
-use signer_rest_api::Worker;
+use signer_protocol::schema_registry::SchemaRegistry;
+use signer_protocol::telemetry;
+use signer_protocol::{PayloadCodec, PayloadFormat};
+use signer_rest_api::config::KafkaConfig;
+use signer_rest_api::resp_topic::{RespTopic, RespTopicConfig};
+use signer_rest_api::rest::RouterConfig;
+use signer_rest_api::transport::{self, KafkaTransport};
+use signer_rest_api::verify::PublicKeyCache;
+use signer_rest_api::{RestartPolicy, Worker};
 use std::env;
+use std::sync::Arc;
+use std::time::Duration;
 
 // Use Jemalloc only for musl-64 bits platforms
 #[cfg(all(target_env = "musl", target_pointer_width = "64"))]
@@ -8,24 +18,220 @@ static ALLOC: jemallocator::Jemalloc = jemallocator::Jemalloc;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    tracing_subscriber::fmt::init();
+    telemetry::init("signer-rest-api")?;
 
-    let brokers =
-        env::var("SIGNER_REST_API_KAFKA_BROKERS").unwrap_or_else(|_| "127.0.0.1:9092".to_string());
+    let kafka_config = KafkaConfig::from_env()?;
     let req_topic =
         env::var("SIGNER_REST_API_REQ_TOPIC").unwrap_or_else(|_| "signer.v1".to_string());
-    let res_topic = env::var("SIGNER_REST_API_RES_TOPIC")?; //This is required
+    // "topic": own response topic, "partition": own partition of shared response topic
+    let resp_routing =
+        env::var("SIGNER_REST_API_RESP_ROUTING").unwrap_or_else(|_| "topic".to_string());
+    // topic created by hand, otherwise one is created for this instance
+    let res_topic = env::var("SIGNER_REST_API_RES_TOPIC").ok();
+    // required with "partition" routing
+    let resp_partition = env::var("SIGNER_REST_API_RESP_PARTITION").ok();
+    let instance = env::var("SIGNER_REST_API_INSTANCE_ID")
+        .or_else(|_| env::var("HOSTNAME"))
+        .ok();
+    let mut resp_topic_config = RespTopicConfig::default();
+    if let Ok(partitions) = env::var("SIGNER_REST_API_RESP_TOPIC_PARTITIONS") {
+        resp_topic_config.partitions = partitions.parse()?;
+    }
+    if let Ok(replication) = env::var("SIGNER_REST_API_RESP_TOPIC_REPLICATION") {
+        resp_topic_config.replication = replication.parse()?;
+    }
+    if let Ok(retention_ms) = env::var("SIGNER_REST_API_RESP_TOPIC_RETENTION_MS") {
+        resp_topic_config.retention_ms = Some(retention_ms.parse()?);
+    }
+    let public_keys_dir = env::var("SIGNER_REST_API_PUBLIC_KEYS_DIR").ok();
+    let payload_format: PayloadFormat = env::var("SIGNER_REST_API_PAYLOAD_FORMAT")
+        .unwrap_or_else(|_| "raw".to_string())
+        .parse()
+        .map_err(anyhow::Error::msg)?;
+    let schema_registry_url = env::var("SIGNER_REST_API_SCHEMA_REGISTRY_URL").ok();
+    let mut router_config = RouterConfig::default();
+    if let Ok(max_in_flight) = env::var("SIGNER_REST_API_WS_MAX_IN_FLIGHT") {
+        router_config.ws_max_in_flight = max_in_flight.parse().map_err(|_| {
+            anyhow::anyhow!("SIGNER_REST_API_WS_MAX_IN_FLIGHT must be a positive number")
+        })?;
+    }
+    // how long waiting requests are answered after SIGTERM
+    let drain_timeout = match env::var("SIGNER_REST_API_DRAIN_TIMEOUT_MS") {
+        Ok(drain_timeout_ms) => Duration::from_millis(drain_timeout_ms.parse()?),
+        Err(_) => DEFAULT_DRAIN_TIMEOUT,
+    };
 
-    tracing::info!("SIGNER_REST_API_KAFKA_BROKERS: {}", brokers);
+    let mut restart_policy = RestartPolicy::default();
+    if let Ok(backoff_ms) = env::var("SIGNER_REST_API_RESTART_MIN_BACKOFF_MS") {
+        restart_policy.min_backoff = Duration::from_millis(backoff_ms.parse()?);
+    }
+    if let Ok(backoff_ms) = env::var("SIGNER_REST_API_RESTART_MAX_BACKOFF_MS") {
+        restart_policy.max_backoff = Duration::from_millis(backoff_ms.parse()?);
+    }
+    if let Ok(max_failures) = env::var("SIGNER_REST_API_RESTART_MAX_FAILURES") {
+        restart_policy.max_failures = Some(max_failures.parse()?);
+    }
+
+    tracing::info!("SIGNER_REST_API_KAFKA_BROKERS: {}", kafka_config.brokers);
+    tracing::info!("SIGNER_REST_API_PAYLOAD_FORMAT: {}", payload_format);
     tracing::trace!("trace level enabled");
 
-    let sign_reqester = Worker::spawn_new(&req_topic, &res_topic, &brokers)?;
+    let codec = match (payload_format, schema_registry_url) {
+        (PayloadFormat::Raw, _) => PayloadCodec::Raw,
+        (format, Some(url)) => PayloadCodec::register(format, &SchemaRegistry::new(&url)?).await?,
+        (format, None) => anyhow::bail!(
+            "SIGNER_REST_API_SCHEMA_REGISTRY_URL is required for {} payload format",
+            format
+        ),
+    };
+
+    let mut owned_topic = None;
+    let (res_topic, resp_partition) = match resp_routing.as_str() {
+        "topic" => {
+            let res_topic = match (res_topic, instance) {
+                (Some(res_topic), _) => res_topic,
+                (None, Some(instance)) => {
+                    let topic = RespTopic::create(&kafka_config, &instance, &resp_topic_config).await?;
+                    owned_topic.insert(topic).name().to_string()
+                }
+                (None, None) => anyhow::bail!(
+                    "SIGNER_REST_API_RES_TOPIC or SIGNER_REST_API_INSTANCE_ID (HOSTNAME) is required"
+                ),
+            };
+            tracing::info!("SIGNER_REST_API_RES_TOPIC: {}", res_topic);
+            (res_topic, None)
+        }
+        "partition" => {
+            let res_topic = res_topic.unwrap_or_else(|| SHARED_RESP_TOPIC.to_string());
+            let resp_partition: i32 = match resp_partition {
+                Some(partition) => partition.parse()?,
+                None => anyhow::bail!(
+                    "SIGNER_REST_API_RESP_PARTITION is required with `partition` routing"
+                ),
+            };
+            // responses to a missing partition would be lost
+            let partitions = transport::partition_count(&kafka_config, &res_topic).await?;
+            if !(0..partitions).contains(&resp_partition) {
+                anyhow::bail!(
+                    "SIGNER_REST_API_RESP_PARTITION {} is out of range, `{}` has {} partitions",
+                    resp_partition,
+                    res_topic,
+                    partitions
+                );
+            }
+            tracing::info!(
+                "SIGNER_REST_API_RES_TOPIC: {}, SIGNER_REST_API_RESP_PARTITION: {}",
+                res_topic,
+                resp_partition
+            );
+            (res_topic, Some(resp_partition))
+        }
+        other => anyhow::bail!(
+            "unknown SIGNER_REST_API_RESP_ROUTING `{}`, expected `topic` or `partition`",
+            other
+        ),
+    };
+
+    // owned response topic is deleted however serving ends
+    let mut served: anyhow::Result<()> = async {
+        // fail fast on bad config, later failures are retried by supervisor
+        let new_transport = {
+            let res_topic = res_topic.clone();
+            move || match resp_partition {
+                None => KafkaTransport::new(&req_topic, &res_topic, &kafka_config, codec.clone()),
+                Some(partition) => KafkaTransport::new_partitioned(
+                    &req_topic,
+                    &res_topic,
+                    partition,
+                    &kafka_config,
+                    codec.clone(),
+                ),
+            }
+        };
+        let mut first_transport = Some(new_transport()?);
+        let (sign_reqester, supervisor) = Worker::spawn_supervised(
+            res_topic,
+            resp_partition,
+            move || match first_transport.take() {
+                Some(transport) => Ok(transport),
+                None => new_transport(),
+            },
+            restart_policy,
+        );
+
+        let public_keys = match public_keys_dir {
+            Some(dir) => PublicKeyCache::from_dir(dir)?,
+            None => PublicKeyCache::default(),
+        };
+        if public_keys.is_empty() {
+            tracing::warn!("no public keys loaded, every signature will fail verification");
+        }
+
+        let router =
+            signer_rest_api::rest::router(sign_reqester, Arc::new(public_keys), router_config);
+
+        let shutdown = supervisor.shutdown_handle();
+        let server = axum::Server::bind(&"0.0.0.0:80".parse().unwrap())
+            .serve(router.into_make_service())
+            .with_graceful_shutdown(async move {
+                shutdown_signal().await;
+                shutdown.shutdown(drain_timeout);
+            });
+        let supervised = supervisor.wait();
+        tokio::pin!(supervised);
+        let supervisor_result = tokio::select! {
+            served = server => {
+                served?;
+                // WebSockets may still be open, worker stops once they are closed and drained
+                supervised.await
+            }
+            // supervisor ends before server only when it gave up, as router keeps the requester
+            supervised = &mut supervised => supervised,
+        };
+        supervisor_result.map_err(Into::into)
+    }
+    .await;
+
+    if let Some(topic) = owned_topic {
+        if let Err(err) = topic.delete().await {
+            tracing::error!("failed to delete response topic: {}", err);
+            served = served.and(Err(err.into()));
+        }
+    }
+    telemetry::shutdown();
+
+    // exit with error so k8s restarts the pod
+    served
+}
+
+/// Default of `SIGNER_REST_API_DRAIN_TIMEOUT_MS`, long enough for requests waiting the default
+/// sign timeout
+const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Response topic shared by instances in `partition` routing mode
+const SHARED_RESP_TOPIC: &str = "signer.v1.resp";
 
-    let router = signer_rest_api::rest::router(sign_reqester);
+/// Ctrl-C or SIGTERM sent by k8s when pod is stopped
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("failed to listen for ctrl-c");
+    };
 
-    axum::Server::bind(&"0.0.0.0:80".parse().unwrap())
-        .serve(router.into_make_service())
-        .await?;
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
 
-    Ok(())
+    tokio::select! {
+        _ = ctrl_c => (),
+        _ = terminate => (),
+    }
+    tracing::info!("shutting down");
 }
@@ -0,0 +1,69 @@
+//! Prometheus metrics of sign requests, served at `/metrics`
+
+use prometheus::{Histogram, HistogramOpts, IntCounter, IntCounterVec, IntGauge, Opts};
+use signer_protocol::metrics::MetricsRegistry;
+
+/// Metrics of single `Worker`, registered in its own [`MetricsRegistry`]
+#[derive(Debug, Clone)]
+pub struct Metrics {
+    registry: MetricsRegistry,
+    /// Finished sign requests by `result`: `ok`, `timeout`, `rejected`, `transport` or `unavailable`
+    pub requests: IntCounterVec,
+    /// Requests waiting for response
+    pub waiting_reqs: IntGauge,
+    /// Time from `SignRequester::start_req` to resolution of the promise
+    pub latency: Histogram,
+    /// Requests which couldn't be produced to request topic
+    pub produce_errors: IntCounter,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        let registry = MetricsRegistry::default();
+        let requests = registry.register(
+            IntCounterVec::new(
+                Opts::new(
+                    "signer_rest_api_sign_requests_total",
+                    "Finished sign requests by result",
+                ),
+                &["result"],
+            )
+            .expect("valid metric"),
+        );
+        let waiting_reqs = registry.register(
+            IntGauge::new(
+                "signer_rest_api_waiting_requests",
+                "Sign requests waiting for response",
+            )
+            .expect("valid metric"),
+        );
+        let latency = registry.register(
+            Histogram::with_opts(HistogramOpts::new(
+                "signer_rest_api_sign_latency_seconds",
+                "Time from start of sign request to its result",
+            ))
+            .expect("valid metric"),
+        );
+        let produce_errors = registry.register(
+            IntCounter::new(
+                "signer_rest_api_kafka_produce_errors_total",
+                "Sign requests which couldn't be produced to Kafka",
+            )
+            .expect("valid metric"),
+        );
+
+        Self {
+            registry,
+            requests,
+            waiting_reqs,
+            latency,
+            produce_errors,
+        }
+    }
+}
+
+impl Metrics {
+    pub fn registry(&self) -> &MetricsRegistry {
+        &self.registry
+    }
+}
@@ -0,0 +1,98 @@
+//! Response topic owned by single `signer-rest-api` instance
+//!
+//! Instead of topics created by hand for every instance, each instance creates
+//! `signer.v1.resp.<instance>` at startup and deletes it on graceful shutdown.
+
+use rdkafka::admin::{AdminClient, AdminOptions, NewTopic, TopicReplication};
+use rdkafka::client::DefaultClientContext;
+use rdkafka::error::{KafkaError, RDKafkaErrorCode};
+use std::time::Duration;
+
+use crate::config::KafkaConfig;
+
+/// Prefix of names of created topics, followed by instance name
+pub const RESP_TOPIC_PREFIX: &str = "signer.v1.resp.";
+
+#[derive(Debug, Clone)]
+pub struct RespTopicConfig {
+    pub partitions: i32,
+    pub replication: i32,
+    /// `retention.ms` of the topic, broker default when `None`
+    pub retention_ms: Option<u64>,
+}
+
+impl Default for RespTopicConfig {
+    fn default() -> Self {
+        Self {
+            partitions: 1,
+            replication: 1,
+            // responses are useless after requester stopped waiting
+            retention_ms: Some(60 * 60 * 1000),
+        }
+    }
+}
+
+/// Topic created by [`RespTopic::create`], deleted by [`RespTopic::delete`]
+pub struct RespTopic {
+    name: String,
+    admin: AdminClient<DefaultClientContext>,
+}
+
+impl RespTopic {
+    /// Create `signer.v1.resp.<instance>` topic
+    ///
+    /// Topic left behind by previous incarnation of the instance (which didn't shut down
+    /// gracefully) is reused.
+    pub async fn create(
+        kafka_config: &KafkaConfig,
+        instance: &str,
+        config: &RespTopicConfig,
+    ) -> Result<Self, KafkaError> {
+        let name = format!("{}{}", RESP_TOPIC_PREFIX, instance);
+        let admin: AdminClient<DefaultClientContext> = kafka_config.client_config().create()?;
+
+        let retention_ms = config.retention_ms.map(|ms| ms.to_string());
+        let mut new_topic = NewTopic::new(
+            &name,
+            config.partitions,
+            TopicReplication::Fixed(config.replication),
+        );
+        if let Some(retention_ms) = &retention_ms {
+            new_topic = new_topic.set("retention.ms", retention_ms);
+        }
+
+        let results = admin.create_topics(&[new_topic], &admin_options()).await?;
+        for result in results {
+            match result {
+                Ok(_) => tracing::info!("created response topic `{}`", name),
+                Err((_, RDKafkaErrorCode::TopicAlreadyExists)) => {
+                    tracing::warn!("response topic `{}` already exists, reusing it", name)
+                }
+                Err((_, code)) => return Err(KafkaError::AdminOp(code)),
+            }
+        }
+
+        Ok(Self { name, admin })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Delete the topic. Responses which weren't read yet are lost.
+    pub async fn delete(self) -> Result<(), KafkaError> {
+        let results = self
+            .admin
+            .delete_topics(&[&self.name], &admin_options())
+            .await?;
+        for result in results {
+            result.map_err(|(_, code)| KafkaError::AdminOp(code))?;
+        }
+        tracing::info!("deleted response topic `{}`", self.name);
+        Ok(())
+    }
+}
+
+fn admin_options() -> AdminOptions {
+    AdminOptions::new().operation_timeout(Some(Duration::from_secs(10)))
+}
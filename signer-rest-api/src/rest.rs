@@ -1,31 +1,112 @@
 //! Represent REST API
 
-use std::time::Duration;
+use std::num::NonZeroUsize;
+use std::sync::Arc;
+
+mod ws;
 
 use axum::{
     extract::{
         ws::{Message, WebSocket},
         WebSocketUpgrade,
     },
-    response::{Html, IntoResponse},
-    routing::get,
-    Router,
+    http::{header, HeaderMap, StatusCode},
+    response::{Headers, Html, IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
 };
+use serde::{Deserialize, Serialize};
+use signer_protocol::ErrorCode;
 
 use tower_http::trace::TraceLayer;
 
-use crate::worker::SignRequester;
+use crate::metrics::Metrics;
+use crate::signed_topic_consumer::TopicConsumeErr;
+use crate::supervisor::{Health, WorkerState};
+use crate::verify::{PublicKeyCache, VerifyReq, VerifyResp};
+use crate::worker::{SignErr, SignPromiseRx, SignRequester};
+use crate::MsgSigned;
+
+#[derive(Debug, Clone)]
+pub struct RouterConfig {
+    /// Maximum number of sign requests waiting for response on single WebSocket connection
+    pub ws_max_in_flight: NonZeroUsize,
+}
+
+impl Default for RouterConfig {
+    fn default() -> Self {
+        Self {
+            ws_max_in_flight: NonZeroUsize::new(64).unwrap(),
+        }
+    }
+}
 
-pub fn router(requester: SignRequester) -> Router {
+pub fn router(requester: SignRequester, keys: Arc<PublicKeyCache>, config: RouterConfig) -> Router {
+    let ws_keys = keys.clone();
+    let http_requester = requester.clone();
+    let health = requester.health().clone();
+    let ready_health = health.clone();
+    let metrics = requester.metrics().clone();
     Router::new()
+        .route("/metrics", get(move || metrics_handler(metrics.clone())))
+        .route("/healthz", get(move || healthz(health.clone())))
+        .route("/readyz", get(move || readyz(ready_health.clone())))
         .route("/sign", get(sign_index))
         .route(
             "/sign/ws",
-            get(move |ws| ws_handler(ws, Clone::clone(&requester))),
+            get(move |ws, headers: HeaderMap| {
+                ws::ws_handler(
+                    ws,
+                    headers,
+                    Clone::clone(&requester),
+                    config.ws_max_in_flight.get(),
+                )
+            }),
+        )
+        .route(
+            "/v1/sign",
+            post(move |Json(req)| sign_handler(req, http_requester.clone())),
+        )
+        .route(
+            "/verify",
+            post(move |Json(req)| verify_handler(req, keys.clone())),
+        )
+        .route(
+            "/verify/ws",
+            get(move |ws| verify_ws_handler(ws, ws_keys.clone())),
         )
         .layer(TraceLayer::new_for_http())
 }
 
+async fn metrics_handler(metrics: Metrics) -> impl IntoResponse {
+    (
+        Headers([(header::CONTENT_TYPE, signer_protocol::metrics::CONTENT_TYPE)]),
+        metrics.registry().encode(),
+    )
+}
+
+/// Liveness, fails only when worker stopped for good
+async fn healthz(health: Health) -> impl IntoResponse {
+    match health.state() {
+        WorkerState::Stopped => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(serde_json::json!({ "status": "stopped" })),
+        ),
+        _ => (StatusCode::OK, Json(serde_json::json!({ "status": "ok" }))),
+    }
+}
+
+/// Readiness, requests can be signed right now
+async fn readyz(health: Health) -> impl IntoResponse {
+    match health.check_ready().await {
+        Ok(()) => (StatusCode::OK, Json(serde_json::json!({ "status": "ok" }))),
+        Err(reason) => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(serde_json::json!({ "status": "not_ready", "reason": reason })),
+        ),
+    }
+}
+
 async fn sign_index() -> Html<String> {
     let fronted = r#"
     <!DOCTYPE html>
@@ -40,10 +121,11 @@ async fn sign_index() -> Html<String> {
 <!-- div with messages -->
 <div id="messages"></div>
   <script>
-  let socket = new WebSocket("ws://" + location.host + "/sign/ws");
+  let socket = new WebSocket("ws://" + location.host + "/sign/ws", "signer.v1.json");
+  let nextId = 0;
 // send message from the form
 document.forms.publish.onsubmit = function() {
-  let outgoingMessage = this.message.value;
+  let outgoingMessage = JSON.stringify({ v: 1, id: String(nextId++), message: this.message.value });
 
   socket.send(outgoingMessage);
   return false;
@@ -51,7 +133,10 @@ document.forms.publish.onsubmit = function() {
 
 // message received - show the message in div#messages
 socket.onmessage = function(event) {
-  let message = event.data;
+  let resp = JSON.parse(event.data);
+  let message = resp.status === "ok"
+    ? `#${resp.id} ok: ${resp.signature} (key_id: ${resp.key_id})`
+    : `#${resp.id} error ${resp.error.code}: ${resp.error.message}`;
 
   let messageElem = document.createElement('div');
   messageElem.textContent = message;
@@ -66,66 +151,196 @@ socket.onmessage = function(event) {
     Html::from(fronted)
 }
 
-async fn ws_handler(ws: WebSocketUpgrade, requester: SignRequester) -> impl IntoResponse {
-    ws.on_upgrade(|socket| singn_ws_kafka_handler(socket, requester))
-}
-
-async fn singn_ws_kafka_handler(mut socket: WebSocket, requester: SignRequester) {
-    while let Some(msg) = socket.recv().await {
-        let promise_sign_msg = if let Ok(msg) = msg {
-            match msg {
-                Message::Text(t) => {
-                    println!("client send msg to sign: {:?}", t);
-                    match requester.start_req(t).await {
-                        Ok(promise_sign_msg) => promise_sign_msg,
-                        Err(_) => todo!(), // server error
-                    }
-                }
-                Message::Binary(_) => {
-                    todo!()
-                }
-                Message::Ping(_) => continue,
-                Message::Pong(_) => continue,
-                Message::Close(_) => {
-                    println!("client disconnected");
-                    return;
-                }
-            }
-        } else {
-            //"client disconnected"
-            return;
-        };
+#[derive(Debug, thiserror::Error)]
+enum SignFailure {
+    #[error("signer unavailable")]
+    Unavailable,
+    #[error("timeout")]
+    Timeout,
+    #[error("internal error")]
+    Internal,
+    #[error("kafka error")]
+    Kafka(#[source] TopicConsumeErr),
+    #[error("signer rejected request ({code}): {message}")]
+    Rejected { code: ErrorCode, message: String },
+}
+
+/// Wait for `promise` to be resolved, `Worker` takes care of request deadline
+async fn await_signed(promise: SignPromiseRx) -> Result<MsgSigned, SignFailure> {
+    match promise.await {
+        Err(_recv_err) => Err(SignFailure::Internal),
+        Ok(Err(SignErr::Timeout)) => Err(SignFailure::Timeout),
+        Ok(Err(SignErr::Unavailable)) => Err(SignFailure::Unavailable),
+        Ok(Err(SignErr::Transport(err))) => Err(SignFailure::Kafka(err)),
+        Ok(Err(SignErr::Rejected { code, message })) => {
+            Err(SignFailure::Rejected { code, message })
+        }
+        Ok(Ok(signed_msg)) => Ok(signed_msg),
+    }
+}
 
-        // prepare response
-        //TODO: SingRequester should be able to handle all this error internally
-        let text_to_send = async move {
-            // handle timeout
-            let fut_res = match tokio::time::timeout(Duration::from_secs(5), promise_sign_msg).await
-            {
-                Ok(fut_res) => fut_res,
-                Err(_elapsed) => return "error: timeout".to_string(),
-            };
-
-            // resolve receiver (promise_sign_msg)
-            let fut_res = match fut_res {
-                Ok(fut_res) => fut_res,
-                Err(_recv_err) => return "error: internal error".to_string(),
-            };
-
-            match fut_res {
-                Ok(signed_msg) => {
-                    format!("ok: {}", signed_msg.signed_msg())
-                }
-                Err(err) => {
-                    format!("error: {:?}", err)
-                }
+/// How message is encoded in JSON string
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MessageEncoding {
+    #[default]
+    Utf8,
+    Base64,
+}
+
+impl MessageEncoding {
+    /// Decode `message` into bytes that will be signed
+    fn decode(self, message: String) -> Result<Vec<u8>, ErrorBody> {
+        match self {
+            MessageEncoding::Utf8 => Ok(message.into_bytes()),
+            MessageEncoding::Base64 => base64::decode(&message)
+                .map_err(|_| ErrorBody::new("bad_encoding", "message is not valid base64")),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct SignReq {
+    message: String,
+    #[serde(default)]
+    encoding: MessageEncoding,
+}
+
+#[derive(Debug, Serialize)]
+struct SignResp {
+    msg_id: String,
+    resp_id: String,
+    key_id: String,
+    /// base64 encoded signature
+    signature: String,
+}
+
+impl From<MsgSigned> for SignResp {
+    fn from(signed_msg: MsgSigned) -> Self {
+        Self {
+            msg_id: signed_msg.msg_id().to_string(),
+            resp_id: signed_msg.resp_id().to_string(),
+            key_id: signed_msg.key_id().to_string(),
+            signature: base64::encode(signed_msg.signed_msg()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct ErrorBody {
+    code: &'static str,
+    message: String,
+}
+
+impl ErrorBody {
+    fn new(code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<SignFailure> for ErrorBody {
+    fn from(failure: SignFailure) -> Self {
+        match failure {
+            SignFailure::Unavailable => ErrorBody::new("unavailable", "signer is not available"),
+            SignFailure::Timeout => ErrorBody::new("timeout", "signer didn't respond in time"),
+            SignFailure::Internal => ErrorBody::new("internal", "internal error"),
+            SignFailure::Kafka(err) => {
+                tracing::warn!("sign request failed: {:?}", err);
+                ErrorBody::new("kafka", "failed to pass request to signer")
             }
+            // code of signer failure response is passed as is
+            SignFailure::Rejected { code, message } => ErrorBody::new(code.as_str(), message),
         }
-        .await;
+    }
+}
+
+/// Error returned by JSON endpoints as `{"error": {"code": ..., "message": ...}}`
+#[derive(Debug)]
+struct ApiError {
+    status: StatusCode,
+    body: ErrorBody,
+}
 
-        match socket.send(Message::Text(text_to_send)).await {
-            Ok(_) => (),
-            Err(_disconnected) => return,
+impl From<SignFailure> for ApiError {
+    fn from(failure: SignFailure) -> Self {
+        let status = match failure {
+            SignFailure::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+            SignFailure::Timeout => StatusCode::GATEWAY_TIMEOUT,
+            SignFailure::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+            SignFailure::Kafka(_) | SignFailure::Rejected { .. } => StatusCode::BAD_GATEWAY,
+        };
+        Self {
+            status,
+            body: ErrorBody::from(failure),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = Json(serde_json::json!({ "error": self.body }));
+        (self.status, body).into_response()
+    }
+}
+
+async fn sign_handler(req: SignReq, requester: SignRequester) -> Result<Json<SignResp>, ApiError> {
+    let msg = req.encoding.decode(req.message).map_err(|body| ApiError {
+        status: StatusCode::UNPROCESSABLE_ENTITY,
+        body,
+    })?;
+
+    let promise = requester
+        .start_req(msg)
+        .await
+        .map_err(|()| SignFailure::Unavailable)?;
+    let signed_msg = await_signed(promise).await?;
+
+    Ok(Json(SignResp::from(signed_msg)))
+}
+
+fn verify(req: VerifyReq, keys: &PublicKeyCache) -> VerifyResp {
+    match req.encoding.decode(req.message) {
+        Ok(msg) => VerifyResp::from(keys.verify(&req.key_id, &msg, &req.signature)),
+        Err(err) => VerifyResp {
+            valid: false,
+            reason: Some(err.message),
+        },
+    }
+}
+
+async fn verify_handler(req: VerifyReq, keys: Arc<PublicKeyCache>) -> Json<VerifyResp> {
+    Json(verify(req, &keys))
+}
+
+async fn verify_ws_handler(ws: WebSocketUpgrade, keys: Arc<PublicKeyCache>) -> impl IntoResponse {
+    ws.on_upgrade(|socket| verify_ws(socket, keys))
+}
+
+/// Each text frame is JSON encoded `VerifyReq` and is answered with JSON encoded `VerifyResp`
+async fn verify_ws(mut socket: WebSocket, keys: Arc<PublicKeyCache>) {
+    while let Some(Ok(msg)) = socket.recv().await {
+        let resp = match msg {
+            Message::Text(t) => match serde_json::from_str::<VerifyReq>(&t) {
+                Ok(req) => verify(req, &keys),
+                Err(err) => VerifyResp {
+                    valid: false,
+                    reason: Some(format!("malformed request: {}", err)),
+                },
+            },
+            Message::Binary(_) => VerifyResp {
+                valid: false,
+                reason: Some("expected text frame".to_string()),
+            },
+            Message::Ping(_) | Message::Pong(_) => continue,
+            Message::Close(_) => return,
+        };
+
+        let text = serde_json::to_string(&resp).expect("VerifyResp is always serializable");
+        if socket.send(Message::Text(text)).await.is_err() {
+            return;
         }
     }
 }
@@ -0,0 +1,305 @@
+//! Sign messages over WebSocket
+//!
+//! Protocol is negotiated with `Sec-WebSocket-Protocol` header:
+//! - `signer.v1.json` (default) -- every text frame is JSON `Request` answered with JSON `Response`.
+//!   Many requests can be in flight at once, responses are send as soon as they are ready and are
+//!   matched with requests by `id`.
+//! - `signer.text` -- legacy mode. Text frame is message to sign answered with `ok: ...` or `error: ...`
+//!
+//! In both modes binary frame is raw message to sign. It's answered with binary frame containing raw
+//! signature or with error in text frame. Binary frame has no correlation id, so in JSON mode binary
+//! frames are answered in the order they were received (still interleaved with JSON responses).
+//!
+//! When worker is shutting down we stop reading frames, send responses of requests in flight and
+//! close the socket with code 1001 (going away).
+
+use axum::{
+    extract::{
+        ws::{CloseFrame, Message, WebSocket},
+        WebSocketUpgrade,
+    },
+    http::{header, HeaderMap},
+    response::IntoResponse,
+};
+use futures::future::{BoxFuture, FutureExt};
+use futures::stream::{FuturesOrdered, FuturesUnordered, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::select;
+
+use super::{await_signed, ErrorBody, MessageEncoding, SignFailure, SignResp};
+use crate::worker::SignRequester;
+
+/// Version of JSON envelope
+const PROTOCOL_VERSION: u32 = 1;
+
+/// Close code telling client that server is going away
+const CLOSE_GOING_AWAY: u16 = 1001;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WsProtocol {
+    Json,
+    Text,
+}
+
+impl WsProtocol {
+    /// Supported protocols in order of preference
+    const ALL: [WsProtocol; 2] = [WsProtocol::Json, WsProtocol::Text];
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            WsProtocol::Json => "signer.v1.json",
+            WsProtocol::Text => "signer.text",
+        }
+    }
+
+    /// Pick protocol requested by client. `Json` is used when client didn't ask for any.
+    fn negotiate(headers: &HeaderMap) -> Self {
+        let requested = match headers
+            .get(header::SEC_WEBSOCKET_PROTOCOL)
+            .and_then(|v| v.to_str().ok())
+        {
+            Some(requested) => requested,
+            None => return WsProtocol::Json,
+        };
+
+        Self::ALL
+            .into_iter()
+            .find(|protocol| {
+                requested
+                    .split(',')
+                    .any(|req_protocol| req_protocol.trim() == protocol.as_str())
+            })
+            .unwrap_or(WsProtocol::Json)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Request {
+    v: u32,
+    /// Correlation id chosen by client. It's copied to the response.
+    #[serde(default)]
+    id: Option<String>,
+    message: String,
+    #[serde(default)]
+    encoding: MessageEncoding,
+}
+
+/// Used to recover correlation id from request that can't be parsed
+#[derive(Debug, Deserialize)]
+struct RequestId {
+    id: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
+enum Outcome {
+    Ok(SignResp),
+    Error { error: ErrorBody },
+}
+
+#[derive(Debug, Serialize)]
+struct Response {
+    v: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    #[serde(flatten)]
+    outcome: Outcome,
+}
+
+impl Response {
+    fn ok(id: Option<String>, signed: SignResp) -> Self {
+        Self {
+            v: PROTOCOL_VERSION,
+            id,
+            outcome: Outcome::Ok(signed),
+        }
+    }
+
+    fn error(id: Option<String>, error: ErrorBody) -> Self {
+        Self {
+            v: PROTOCOL_VERSION,
+            id,
+            outcome: Outcome::Error { error },
+        }
+    }
+
+    fn into_message(self) -> Message {
+        Message::Text(serde_json::to_string(&self).expect("Response is always serializable"))
+    }
+}
+
+pub(super) async fn ws_handler(
+    ws: WebSocketUpgrade,
+    headers: HeaderMap,
+    requester: SignRequester,
+    max_in_flight: usize,
+) -> impl IntoResponse {
+    let protocol = WsProtocol::negotiate(&headers);
+    ws.protocols([protocol.as_str()])
+        .on_upgrade(move |socket| async move {
+            match protocol {
+                WsProtocol::Json => json_ws_kafka_handler(socket, requester, max_in_flight).await,
+                WsProtocol::Text => singn_ws_kafka_handler(socket, requester).await,
+            }
+        })
+}
+
+/// Handle JSON requests concurrently
+///
+/// When `max_in_flight` requests are waiting for signature we stop reading new frames until one of them is resolved.
+async fn json_ws_kafka_handler(
+    mut socket: WebSocket,
+    requester: SignRequester,
+    max_in_flight: usize,
+) {
+    let mut in_flight: FuturesUnordered<BoxFuture<'static, Message>> = FuturesUnordered::new();
+    // answered in order, see module docs
+    let mut binary_in_flight: FuturesOrdered<BoxFuture<'static, Message>> = FuturesOrdered::new();
+    let mut health = requester.health().clone();
+    let mut stopping = false;
+
+    loop {
+        select! {
+            () = health.stopping(), if !stopping => stopping = true,
+            msg = socket.recv(), if !stopping && in_flight.len() + binary_in_flight.len() < max_in_flight => {
+                let msg = match msg {
+                    Some(Ok(msg)) => msg,
+                    _ => return, // client disconnected
+                };
+                match msg {
+                    Message::Text(t) => {
+                        let requester = requester.clone();
+                        in_flight.push(async move { sign_json(t, requester).await.into_message() }.boxed())
+                    }
+                    Message::Binary(data) => binary_in_flight.push_back(sign_binary(data, requester.clone()).boxed()),
+                    Message::Ping(_) | Message::Pong(_) => (),
+                    Message::Close(_) => return,
+                }
+            },
+            Some(resp) = in_flight.next() => {
+                if socket.send(resp).await.is_err() {
+                    return;
+                }
+            },
+            Some(resp) = binary_in_flight.next() => {
+                if socket.send(resp).await.is_err() {
+                    return;
+                }
+            },
+            // stopping and nothing in flight
+            else => return close_going_away(socket).await,
+        }
+    }
+}
+
+async fn close_going_away(mut socket: WebSocket) {
+    let frame = CloseFrame {
+        code: CLOSE_GOING_AWAY,
+        reason: "server is shutting down".into(),
+    };
+    let _ = socket.send(Message::Close(Some(frame))).await;
+}
+
+async fn sign_json(text: String, requester: SignRequester) -> Response {
+    let req: Request = match serde_json::from_str(&text) {
+        Ok(req) => req,
+        Err(err) => {
+            let id = serde_json::from_str::<RequestId>(&text)
+                .ok()
+                .and_then(|req| req.id);
+            return Response::error(id, ErrorBody::new("bad_request", err.to_string()));
+        }
+    };
+
+    if req.v != PROTOCOL_VERSION {
+        return Response::error(
+            req.id,
+            ErrorBody::new(
+                "unsupported_version",
+                format!("only version {} is supported", PROTOCOL_VERSION),
+            ),
+        );
+    }
+
+    let msg = match req.encoding.decode(req.message) {
+        Ok(msg) => msg,
+        Err(err) => return Response::error(req.id, err),
+    };
+
+    let promise = match requester.start_req(msg).await {
+        Ok(promise) => promise,
+        Err(()) => return Response::error(req.id, SignFailure::Unavailable.into()),
+    };
+
+    match await_signed(promise).await {
+        Ok(signed_msg) => Response::ok(req.id, SignResp::from(signed_msg)),
+        Err(failure) => Response::error(req.id, failure.into()),
+    }
+}
+
+async fn sign_binary(msg: Vec<u8>, requester: SignRequester) -> Message {
+    let promise = match requester.start_req(msg).await {
+        Ok(promise) => promise,
+        Err(()) => return Response::error(None, SignFailure::Unavailable.into()).into_message(),
+    };
+
+    match await_signed(promise).await {
+        Ok(signed_msg) => Message::Binary(signed_msg.signed_msg().to_vec()),
+        Err(failure) => Response::error(None, failure.into()).into_message(),
+    }
+}
+
+async fn singn_ws_kafka_handler(mut socket: WebSocket, requester: SignRequester) {
+    let mut health = requester.health().clone();
+    loop {
+        let msg = select! {
+            () = health.stopping() => return close_going_away(socket).await,
+            msg = socket.recv() => match msg {
+                Some(msg) => msg,
+                None => return,
+            },
+        };
+        let (promise_sign_msg, binary) = if let Ok(msg) = msg {
+            match msg {
+                Message::Text(t) => {
+                    tracing::debug!("client send msg to sign: {:?}", t);
+                    (requester.start_req(t.into_bytes()).await, false)
+                }
+                Message::Binary(data) => (requester.start_req(data).await, true),
+                Message::Ping(_) => continue,
+                Message::Pong(_) => continue,
+                Message::Close(_) => {
+                    tracing::debug!("client disconnected");
+                    return;
+                }
+            }
+        } else {
+            //"client disconnected"
+            return;
+        };
+
+        // prepare response
+        let signed = match promise_sign_msg {
+            Ok(promise_sign_msg) => await_signed(promise_sign_msg).await,
+            Err(()) => Err(SignFailure::Unavailable),
+        };
+        let msg_to_send = match signed {
+            Ok(signed_msg) if binary => Message::Binary(signed_msg.signed_msg().to_vec()),
+            Ok(signed_msg) => Message::Text(format!(
+                "ok: {} (key_id: {})",
+                base64::encode(signed_msg.signed_msg()),
+                signed_msg.key_id()
+            )),
+            // Kafka error is logged, client gets the same message as JSON endpoints send
+            Err(err @ SignFailure::Kafka(_)) => {
+                Message::Text(format!("error: {}", ErrorBody::from(err).message))
+            }
+            Err(err) => Message::Text(format!("error: {}", err)),
+        };
+
+        match socket.send(msg_to_send).await {
+            Ok(_) => (),
+            Err(_disconnected) => return,
+        }
+    }
+}
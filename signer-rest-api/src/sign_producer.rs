@@ -1,11 +1,8 @@
 use std::time::Duration;
 
 use futures::StreamExt;
-use rdkafka::{
-    message::{Headers, OwnedHeaders},
-    producer::{FutureProducer, FutureRecord},
-    Message,
-};
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use signer_protocol::PayloadCodec;
 use tokio::sync::mpsc::{self, Sender};
 use tokio_stream::wrappers::ReceiverStream;
 
@@ -14,6 +11,7 @@ use crate::{signed_topic_consumer::TopicConsumeErr, MsgToSign};
 pub struct SignProducer {
     topic: String,
     timeout: Duration,
+    codec: PayloadCodec,
 
     requests: ReceiverStream<MsgToSign>,
     inner: FutureProducer,
@@ -24,6 +22,7 @@ impl SignProducer {
     pub fn new(
         topic: impl Into<String>,
         producer: FutureProducer,
+        codec: PayloadCodec,
         sending_err: Sender<TopicConsumeErr>,
     ) -> (SignProducer, Sender<MsgToSign>) {
         let (tx, rx) = mpsc::channel(1024);
@@ -31,6 +30,7 @@ impl SignProducer {
             Self {
                 topic: topic.into(),
                 timeout: Duration::from_secs(5),
+                codec,
                 requests: ReceiverStream::new(rx),
                 inner: producer,
                 sending_err,
@@ -44,16 +44,13 @@ impl SignProducer {
             let topic = self.topic.clone();
             let se = self.sending_err.clone();
             let producer = self.inner.clone();
+            let payload = self.codec.encode_request(&req);
 
             //TODO: Instead of spawning new task for each future we could use futures::FutresUnordered
             tokio::spawn(async move {
-                let record = FutureRecord::<str, str>::to(&topic)
-                    .headers(
-                        OwnedHeaders::new_with_capacity(2)
-                            .add("msg_id", req.msg_id())
-                            .add("resp_topic", req.resp_topic()),
-                    )
-                    .payload(req.msg());
+                let record = FutureRecord::<str, [u8]>::to(&topic)
+                    .headers(req.headers())
+                    .payload(&payload);
 
                 let f = producer.send(record, self.timeout);
 
@@ -61,18 +58,11 @@ impl SignProducer {
                 match r {
                     Ok(_) => (),
                     Err((err, msg)) => {
-                        let (key, value) = msg
-                            .headers()
-                            .unwrap()
-                            .get_as(0)
-                            .expect("msg_id should be present");
-                        assert_eq!(key, "msg_id");
+                        let msg_id = signer_protocol::msg_id(&msg).ok().map(str::to_string);
 
-                        let value: &str = value.expect("value should be String");
-
-                        se.send(TopicConsumeErr::new(Some(value.to_string()), err))
-                            .await
-                            .expect("expected loopback err")
+                        if se.send(TopicConsumeErr::new(msg_id, err)).await.is_err() {
+                            tracing::debug!("nobody waits for sending error, transport is gone");
+                        }
                     }
                 }
             });
@@ -1,10 +1,9 @@
-use rdkafka::message::Headers;
-use rdkafka::Message;
 use tokio_stream::wrappers::ReceiverStream;
 
-use crate::MsgSigned;
+use crate::MsgResp;
 use futures::ready;
 use rdkafka::{consumer::MessageStream, error::KafkaError};
+use signer_protocol::{PayloadCodec, ProtocolError};
 
 use tokio_stream::Stream;
 
@@ -12,22 +11,38 @@ use core::pin::Pin;
 use core::task::{Context, Poll};
 use pin_project_lite::pin_project;
 
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum ConsumeErrSource {
+    #[error(transparent)]
+    Kafka(#[from] KafkaError),
+    /// Response doesn't follow signer protocol
+    #[error(transparent)]
+    Protocol(#[from] ProtocolError),
+}
+
 #[derive(Debug, Clone, thiserror::Error)]
 #[error("failed to receive data from consumer")]
 pub struct TopicConsumeErr {
     msg_id: Option<String>,
     #[source]
-    source_err: KafkaError,
+    source_err: ConsumeErrSource,
 }
 impl TopicConsumeErr {
-    pub fn new(msg_id: Option<String>, source_err: KafkaError) -> Self {
-        Self { msg_id, source_err }
+    pub fn new(msg_id: Option<String>, source_err: impl Into<ConsumeErrSource>) -> Self {
+        Self {
+            msg_id,
+            source_err: source_err.into(),
+        }
     }
 
     /// This error has attached msg_id
     pub fn msg_id(&self) -> Option<&str> {
         self.msg_id.as_deref()
     }
+
+    pub fn source_err(&self) -> &ConsumeErrSource {
+        &self.source_err
+    }
 }
 
 impl From<KafkaError> for TopicConsumeErr {
@@ -40,6 +55,7 @@ pin_project! {
     pub struct SignedTopicConsumer<'a> {
         //TODO: implement alternates between streams similar to tokio_stream::Merge
         _consumer_first: bool,
+        codec: PayloadCodec,
         // waiting for responses from kafka
         #[pin]
         consumer: MessageStream<'a>,
@@ -51,55 +67,52 @@ pin_project! {
 
 pub fn new_signed_topic_consumer(
     consumer: MessageStream<'_>,
+    codec: PayloadCodec,
     sending_err: ReceiverStream<TopicConsumeErr>,
 ) -> SignedTopicConsumer<'_> {
     SignedTopicConsumer {
         _consumer_first: false,
+        codec,
         consumer,
         sending_err,
     }
 }
 
 impl<'a> Stream for SignedTopicConsumer<'a> {
-    type Item = Result<MsgSigned, TopicConsumeErr>;
+    type Item = Result<MsgResp, TopicConsumeErr>;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        let me = self.project();
+        let mut me = self.project();
 
         // TODO implement Fuse and done for both streams
 
-        if let Poll::Ready(item) = me.consumer.poll_next(cx) {
+        while let Poll::Ready(item) = me.consumer.as_mut().poll_next(cx) {
             match item {
-                Some(Ok(raw_msg)) => {
-                    let signed_msg = raw_msg
-                        .payload()
-                        .expect("we assume that response from kafka conains correct format");
-                    let signed_msg =
-                        String::from_utf8(signed_msg.to_owned()).expect("assume String is correct");
-
-                    let headers = raw_msg.headers().expect("expect headers");
-                    let (key, req_msg_id) = headers.get_as(0).expect("msg_id");
-                    assert_eq!(key, "msg_id");
-                    let req_msg_id: &str = req_msg_id.unwrap();
-
-                    let (key, resp_msg_id) = headers.get_as(1).expect("resp_id");
-                    assert_eq!(key, "resp_id");
-                    let resp_msg_id: &str = resp_msg_id.unwrap();
-
-                    let singed_msg =
-                        MsgSigned::new(req_msg_id.to_string(), resp_msg_id.to_string(), signed_msg);
-
-                    return Poll::Ready(Some(Ok(singed_msg)));
-                }
+                Some(Ok(raw_msg)) => match me.codec.decode_resp(&raw_msg) {
+                    Ok(singed_msg) => return Poll::Ready(Some(Ok(singed_msg))),
+                    Err(err) => match signer_protocol::msg_id(&raw_msg) {
+                        Ok(msg_id) => {
+                            let err = TopicConsumeErr::new(Some(msg_id.to_string()), err);
+                            return Poll::Ready(Some(Err(err)));
+                        }
+                        Err(_) => {
+                            tracing::warn!("dropping malformed response: {}", err);
+                            continue;
+                        }
+                    },
+                },
                 Some(Err(err)) => return Poll::Ready(Some(Err(TopicConsumeErr::from(err)))),
-                None => todo!(),
+                // consumer is closed, `Worker` restarts transport
+                None => return Poll::Ready(None),
             }
-        };
+        }
 
         let possible_err = ready!(me.sending_err.poll_next(cx));
         match possible_err {
             Some(err) => Poll::Ready(Some(Err(err))),
-            None => todo!(),
+            // producer is gone, responses to already sent requests can still come from consumer
+            // which registered the waker
+            None => Poll::Pending,
         }
     }
 }
@@ -0,0 +1,268 @@
+//! Keeps `Worker` and tasks of its `Transport` running
+//!
+//! When any transport task stops (or panics) waiting requests fail with [`SignErr::Unavailable`],
+//! the transport is dropped and a new one is created after backoff. Requests coming in the
+//! meantime are rejected right away. After too many failures in a row supervisor gives up and
+//! [`Supervisor::wait`] returns error, so the process can exit and be restarted by k8s.
+//!
+//! [`ShutdownHandle::shutdown`] stops worker gracefully: new requests are rejected, waiting ones
+//! are still answered until they are all resolved or drain timeout passes.
+
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+use crate::transport::{Probe, Transport};
+use crate::worker::{SignErr, Worker};
+
+/// How transport is restarted after failure
+#[derive(Debug, Clone)]
+pub struct RestartPolicy {
+    /// Backoff after first failure, doubled after each next one
+    pub min_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Failures in a row after which supervisor gives up, never when `None`. Transport which
+    /// worked for at least `max_backoff` resets the count.
+    pub max_failures: Option<u32>,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self {
+            min_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(30),
+            max_failures: Some(10),
+        }
+    }
+}
+
+impl RestartPolicy {
+    /// Give up at first failure
+    pub fn never() -> Self {
+        Self {
+            max_failures: Some(0),
+            ..Self::default()
+        }
+    }
+
+    fn backoff(&self, failures: u32) -> Duration {
+        let exp = failures.saturating_sub(1).min(16);
+        self.min_backoff
+            .saturating_mul(1 << exp)
+            .min(self.max_backoff)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorkerState {
+    /// Transport wasn't started yet, requests are queued
+    Starting,
+    Running,
+    /// Transport failed and is restarted after backoff, requests are rejected
+    Restarting,
+    /// Shutting down, requests are rejected but waiting ones are still answered
+    Draining,
+    /// Supervisor gave up, all requesters are gone or it was shut down
+    Stopped,
+}
+
+/// [`Probe`] of currently running transport
+pub(crate) type ProbeSlot = Arc<Mutex<Option<Arc<dyn Probe>>>>;
+
+/// Current [`WorkerState`], cheap to clone and check on every request
+#[derive(Clone)]
+pub struct Health {
+    state: watch::Receiver<WorkerState>,
+    probe: ProbeSlot,
+}
+
+impl std::fmt::Debug for Health {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Health").field(&self.state()).finish()
+    }
+}
+
+impl Health {
+    pub(crate) fn new(state: watch::Receiver<WorkerState>, probe: ProbeSlot) -> Self {
+        Self { state, probe }
+    }
+
+    pub fn state(&self) -> WorkerState {
+        *self.state.borrow()
+    }
+
+    /// Requests can be accepted
+    pub fn is_available(&self) -> bool {
+        matches!(self.state(), WorkerState::Starting | WorkerState::Running)
+    }
+
+    /// Wait until state changes, returns new one
+    pub async fn changed(&mut self) -> WorkerState {
+        // sender is dropped only after `Stopped` was sent
+        let _ = self.state.changed().await;
+        self.state()
+    }
+
+    /// Wait until worker is shutting down or stopped
+    pub async fn stopping(&mut self) {
+        while !matches!(self.state(), WorkerState::Draining | WorkerState::Stopped) {
+            if self.state.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Worker is running and its transport passes [`Probe`]. Returns reason when not ready.
+    pub async fn check_ready(&self) -> Result<(), String> {
+        match self.state() {
+            WorkerState::Running => (),
+            state => return Err(format!("worker is {:?}", state)),
+        }
+        let probe = self.probe.lock().unwrap().clone();
+        match probe {
+            Some(probe) => probe.check().await,
+            None => Ok(()),
+        }
+    }
+}
+
+/// Why transport of `Worker` stopped working
+#[derive(Debug, thiserror::Error)]
+pub enum TransportFailure {
+    #[error("failed to create transport: {0}")]
+    Create(#[source] Box<dyn Error + Send + Sync>),
+    #[error("transport task panicked")]
+    Panicked,
+    #[error("transport task stopped")]
+    Stopped,
+    #[error("transport closed its channel")]
+    Closed,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SupervisorError {
+    #[error("transport failed {failures} times in a row, last time: {last}")]
+    GaveUp {
+        failures: u32,
+        #[source]
+        last: TransportFailure,
+    },
+    #[error("worker panicked")]
+    Panicked,
+}
+
+/// Stops worker gracefully, see [`Supervisor::shutdown_handle`]
+#[derive(Debug, Clone)]
+pub struct ShutdownHandle(pub(crate) mpsc::Sender<Duration>);
+
+impl ShutdownHandle {
+    /// Start shutdown, waiting requests are answered at most for `drain_timeout`
+    ///
+    /// Worker stops when all requesters are dropped and no request is waiting, or after
+    /// `drain_timeout` when the rest of requests fail with [`SignErr::Unavailable`]. Repeated calls
+    /// are ignored.
+    pub fn shutdown(&self, drain_timeout: Duration) {
+        let _ = self.0.try_send(drain_timeout);
+    }
+}
+
+/// Handle of supervisor task, returned by `Worker::spawn*`
+///
+/// Dropping it detaches the task.
+pub struct Supervisor {
+    task: JoinHandle<Result<(), SupervisorError>>,
+    health: Health,
+    shutdown: ShutdownHandle,
+}
+
+impl Supervisor {
+    pub(crate) fn spawn<T, E, F>(worker: Worker, new_transport: F, policy: RestartPolicy) -> Self
+    where
+        T: Transport + 'static,
+        E: Into<Box<dyn Error + Send + Sync>> + 'static,
+        F: FnMut() -> Result<T, E> + Send + 'static,
+    {
+        let health = worker.health();
+        let shutdown = worker.shutdown_handle();
+        let task = tokio::spawn(supervise(worker, new_transport, policy));
+        Self {
+            task,
+            health,
+            shutdown,
+        }
+    }
+
+    pub fn health(&self) -> Health {
+        self.health.clone()
+    }
+
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    /// Wait until worker stops. Returns `Ok` when all requesters were dropped or it was shut down.
+    pub async fn wait(self) -> Result<(), SupervisorError> {
+        self.task.await.map_err(|_| SupervisorError::Panicked)?
+    }
+}
+
+async fn supervise<T, E, F>(
+    mut worker: Worker,
+    mut new_transport: F,
+    policy: RestartPolicy,
+) -> Result<(), SupervisorError>
+where
+    T: Transport,
+    E: Into<Box<dyn Error + Send + Sync>>,
+    F: FnMut() -> Result<T, E>,
+{
+    let mut failures = 0;
+    loop {
+        let started = Instant::now();
+        let channels = new_transport()
+            .map(Transport::start)
+            .map_err(|err| TransportFailure::Create(err.into()));
+        let result = match channels {
+            Ok(channels) => {
+                worker.set_state(WorkerState::Running);
+                worker.work(channels).await
+            }
+            Err(failure) => Err(failure),
+        };
+        let failure = match result {
+            Ok(()) => {
+                tracing::info!("stopping worker");
+                return Ok(());
+            }
+            Err(failure) => failure,
+        };
+
+        worker.fail_waiting(SignErr::Unavailable);
+        if started.elapsed() >= policy.max_backoff {
+            failures = 0;
+        }
+        failures += 1;
+        if policy.max_failures.map_or(false, |max| failures > max) {
+            tracing::error!(
+                "giving up after {} transport failures: {}",
+                failures,
+                failure
+            );
+            return Err(SupervisorError::GaveUp {
+                failures,
+                last: failure,
+            });
+        }
+
+        worker.set_state(WorkerState::Restarting);
+        let backoff = policy.backoff(failures);
+        tracing::warn!("transport failed: {}, restarting in {:?}", failure, backoff);
+        if !worker.reject_reqs_until(Instant::now() + backoff).await {
+            return Ok(());
+        }
+    }
+}
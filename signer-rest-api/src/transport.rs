@@ -0,0 +1,309 @@
+//! Ways of delivering sign requests to `signer-service` and receiving its responses
+//!
+//! [`KafkaTransport`] is used in production. [`InProcessTransport`] connects `Worker` with signer
+//! running in the same process through channels, for local development and integration tests.
+
+use futures::future::BoxFuture;
+use futures::StreamExt;
+use rdkafka::{
+    consumer::{BaseConsumer, Consumer, StreamConsumer},
+    error::KafkaError,
+    producer::{FutureProducer, Producer},
+    types::RDKafkaErrorCode,
+    Offset, TopicPartitionList,
+};
+use signer_protocol::PayloadCodec;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::{self, Receiver, Sender};
+use tokio::task::JoinHandle;
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::config::KafkaConfig;
+use crate::sign_producer::SignProducer;
+use crate::signed_topic_consumer::{new_signed_topic_consumer, TopicConsumeErr};
+use crate::{MsgResp, MsgToSign};
+
+/// Channels returned by started [`Transport`]
+pub struct Channels {
+    /// Request sink. Request which can't be delivered comes back as `Err` through `responses`
+    /// (error loopback).
+    pub requests: Sender<MsgToSign>,
+    /// Responses of signer and looped back errors
+    pub responses: Receiver<Result<MsgResp, TopicConsumeErr>>,
+    /// Tasks spawned by transport. Transport is broken when any of them stops.
+    pub tasks: Vec<JoinHandle<()>>,
+    /// Readiness check of transport, ready when running if `None`
+    pub probe: Option<Arc<dyn Probe>>,
+    /// Awaited on shutdown after `requests` is dropped, so sent requests aren't lost
+    pub flush: Option<BoxFuture<'static, ()>>,
+}
+
+/// Checks whether started transport can pass requests, see `/readyz`
+pub trait Probe: Send + Sync {
+    /// Returns reason when not ready
+    fn check(&self) -> BoxFuture<'_, Result<(), String>>;
+}
+
+pub trait Transport {
+    /// Topic signer should send responses to our requests to
+    fn resp_topic(&self) -> &str;
+
+    /// Partition of shared `resp_topic` signer should send responses to, see
+    /// [`KafkaTransport::new_partitioned`]
+    fn resp_partition(&self) -> Option<i32> {
+        None
+    }
+
+    /// Spawn tasks moving requests and responses. Must be called within tokio runtime.
+    ///
+    /// Tasks are aborted by `Worker` when the transport fails.
+    fn start(self) -> Channels;
+}
+
+pub struct KafkaTransport {
+    req_topic: String,
+    resp_topic: String,
+    resp_partition: Option<i32>,
+    producer: FutureProducer,
+    consumer: Arc<StreamConsumer>,
+    codec: PayloadCodec,
+}
+
+/// How long [`KafkaProbe`] waits for broker metadata
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+/// How long shutdown waits for producer to deliver sign requests
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
+impl KafkaTransport {
+    /// `req_topic` -- is producer topic. One topic for as many application as you wish
+    /// `resp_topic` -- consumer. must be used only by one instance of application
+    /// `config` -- of Kafka clients, consumer group is per instance unless configured otherwise
+    /// `codec` -- payload format of both topics, must match the one used by `signer-service`
+    pub fn new(
+        req_topic: &str,
+        resp_topic: &str,
+        config: &KafkaConfig,
+        codec: PayloadCodec,
+    ) -> Result<Self, KafkaError> {
+        let consumer: StreamConsumer = config
+            .consumer_config(resp_topic, None)
+            .set("enable.auto.commit", "true")
+            .create()?;
+        consumer.subscribe(&[resp_topic])?;
+
+        Self::with_consumer(req_topic, resp_topic, None, config, consumer, codec)
+    }
+
+    /// Like [`KafkaTransport::new`] but `resp_topic` is shared by many instances and this one
+    /// consumes only `resp_partition` of it
+    ///
+    /// Partition is assigned, not subscribed, and read from its end, as only responses to
+    /// requests sent from now on matter. Each instance must use different partition.
+    pub fn new_partitioned(
+        req_topic: &str,
+        resp_topic: &str,
+        resp_partition: i32,
+        config: &KafkaConfig,
+        codec: PayloadCodec,
+    ) -> Result<Self, KafkaError> {
+        let consumer: StreamConsumer = config
+            .consumer_config(resp_topic, Some(resp_partition))
+            .set("enable.auto.commit", "false")
+            .create()?;
+        let mut assignment = TopicPartitionList::new();
+        assignment.add_partition_offset(resp_topic, resp_partition, Offset::End)?;
+        consumer.assign(&assignment)?;
+
+        Self::with_consumer(
+            req_topic,
+            resp_topic,
+            Some(resp_partition),
+            config,
+            consumer,
+            codec,
+        )
+    }
+
+    fn with_consumer(
+        req_topic: &str,
+        resp_topic: &str,
+        resp_partition: Option<i32>,
+        config: &KafkaConfig,
+        consumer: StreamConsumer,
+        codec: PayloadCodec,
+    ) -> Result<Self, KafkaError> {
+        let producer = config.producer_config().create()?;
+
+        Ok(Self {
+            req_topic: req_topic.to_string(),
+            resp_topic: resp_topic.to_string(),
+            resp_partition,
+            producer,
+            consumer: Arc::new(consumer),
+            codec,
+        })
+    }
+}
+
+/// Number of partitions of `topic`, fails when the topic doesn't exist
+pub async fn partition_count(config: &KafkaConfig, topic: &str) -> Result<i32, KafkaError> {
+    let consumer: BaseConsumer = config.client_config().create()?;
+    let topic = topic.to_string();
+    let metadata =
+        tokio::task::spawn_blocking(move || consumer.fetch_metadata(Some(&topic), PROBE_TIMEOUT))
+            .await
+            .expect("fetching metadata panicked")?;
+    let topic = metadata
+        .topics()
+        .first()
+        .ok_or(KafkaError::MetadataFetch(RDKafkaErrorCode::UnknownTopic))?;
+    if let Some(err) = topic.error() {
+        return Err(KafkaError::MetadataFetch(err.into()));
+    }
+    Ok(topic.partitions().len() as i32)
+}
+
+/// Ready when brokers can be reached by producer and consumer has a partition of response topic
+struct KafkaProbe {
+    req_topic: String,
+    resp_topic: String,
+    producer: FutureProducer,
+    consumer: Arc<StreamConsumer>,
+}
+
+impl Probe for KafkaProbe {
+    fn check(&self) -> BoxFuture<'_, Result<(), String>> {
+        Box::pin(async move {
+            let producer = self.producer.clone();
+            let req_topic = self.req_topic.clone();
+            tokio::task::spawn_blocking(move || {
+                producer
+                    .client()
+                    .fetch_metadata(Some(&req_topic), PROBE_TIMEOUT)
+            })
+            .await
+            .map_err(|err| err.to_string())?
+            .map_err(|err| format!("can't reach brokers: {}", err))?;
+
+            let assignment = self.consumer.assignment().map_err(|err| err.to_string())?;
+            if assignment.elements_for_topic(&self.resp_topic).is_empty() {
+                return Err(format!("no partition of `{}` assigned", self.resp_topic));
+            }
+            Ok(())
+        })
+    }
+}
+
+impl Transport for KafkaTransport {
+    fn resp_topic(&self) -> &str {
+        &self.resp_topic
+    }
+
+    fn resp_partition(&self) -> Option<i32> {
+        self.resp_partition
+    }
+
+    fn start(self) -> Channels {
+        let probe = KafkaProbe {
+            req_topic: self.req_topic.clone(),
+            resp_topic: self.resp_topic,
+            producer: self.producer.clone(),
+            consumer: Arc::clone(&self.consumer),
+        };
+        let producer = self.producer.clone();
+        let flush = async move {
+            let _ = tokio::task::spawn_blocking(move || producer.flush(FLUSH_TIMEOUT)).await;
+        };
+        let (loopback_err_tx, loopback_err_rx) = mpsc::channel(1024);
+        let (sign_producer, requests) = SignProducer::new(
+            self.req_topic,
+            self.producer,
+            self.codec.clone(),
+            loopback_err_tx,
+        );
+
+        let (resp_tx, responses) = mpsc::channel(1024);
+        let consumer = self.consumer;
+        let codec = self.codec;
+
+        let producer_task = tokio::spawn(async move {
+            sign_producer.worker().await;
+        });
+
+        let consumer_task = tokio::spawn(async move {
+            let signed_msgs = new_signed_topic_consumer(
+                consumer.stream(),
+                codec,
+                ReceiverStream::new(loopback_err_rx),
+            );
+            futures::pin_mut!(signed_msgs);
+
+            while let Some(resp) = signed_msgs.next().await {
+                if resp_tx.send(resp).await.is_err() {
+                    break; // worker is gone
+                }
+            }
+        });
+
+        Channels {
+            requests,
+            responses,
+            tasks: vec![producer_task, consumer_task],
+            probe: Some(Arc::new(probe)),
+            flush: Some(Box::pin(flush)),
+        }
+    }
+}
+
+/// Transport to signer running in the same process
+///
+/// `requests` and `responses` are the other ends of channels given to signer, for example to
+/// `signer_service::transport::InProcessTransport`. There is single requester so `resp_topic` is
+/// only informative.
+pub struct InProcessTransport {
+    resp_topic: String,
+    requests: Sender<MsgToSign>,
+    responses: Receiver<MsgResp>,
+}
+
+impl InProcessTransport {
+    pub fn new(
+        resp_topic: impl Into<String>,
+        requests: Sender<MsgToSign>,
+        responses: Receiver<MsgResp>,
+    ) -> Self {
+        Self {
+            resp_topic: resp_topic.into(),
+            requests,
+            responses,
+        }
+    }
+}
+
+impl Transport for InProcessTransport {
+    fn resp_topic(&self) -> &str {
+        &self.resp_topic
+    }
+
+    fn start(self) -> Channels {
+        let (resp_tx, responses) = mpsc::channel(1024);
+        let mut signed_msgs = self.responses;
+
+        let task = tokio::spawn(async move {
+            while let Some(resp) = signed_msgs.recv().await {
+                if resp_tx.send(Ok(resp)).await.is_err() {
+                    break; // worker is gone
+                }
+            }
+        });
+
+        Channels {
+            requests: self.requests,
+            responses,
+            tasks: vec![task],
+            probe: None,
+            flush: None,
+        }
+    }
+}
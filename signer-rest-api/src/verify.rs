@@ -0,0 +1,137 @@
+//! Verify signatures with public keys of `signer-service`
+
+use anyhow::Context;
+use ed25519_dalek::pkcs8::DecodePublicKey;
+use p256::ecdsa::signature::Verifier;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::rest::MessageEncoding;
+
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum VerifyErr {
+    #[error("unknown key id `{0}`")]
+    UnknownKey(String),
+    #[error("signature is not valid base64")]
+    MalformedBase64,
+    #[error("malformed signature")]
+    MalformedSignature,
+    #[error("signature doesn't match message")]
+    BadSignature,
+}
+
+enum PublicKey {
+    Ed25519(ed25519_dalek::VerifyingKey),
+    EcdsaP256(p256::ecdsa::VerifyingKey),
+}
+
+impl PublicKey {
+    fn from_public_key_pem(pem: &str) -> anyhow::Result<Self> {
+        if let Ok(key) = ed25519_dalek::VerifyingKey::from_public_key_pem(pem) {
+            return Ok(PublicKey::Ed25519(key));
+        }
+        let key = p256::ecdsa::VerifyingKey::from_public_key_pem(pem)
+            .map_err(|_| anyhow::Error::msg("expected ed25519 or ecdsa-p256 public key"))?;
+        Ok(PublicKey::EcdsaP256(key))
+    }
+
+    fn verify(&self, msg: &[u8], signature: &[u8]) -> Result<(), VerifyErr> {
+        match self {
+            PublicKey::Ed25519(key) => {
+                let signature = ed25519_dalek::Signature::from_slice(signature)
+                    .map_err(|_| VerifyErr::MalformedSignature)?;
+                key.verify_strict(msg, &signature)
+                    .map_err(|_| VerifyErr::BadSignature)
+            }
+            PublicKey::EcdsaP256(key) => {
+                let signature = p256::ecdsa::Signature::from_der(signature)
+                    .map_err(|_| VerifyErr::MalformedSignature)?;
+                key.verify(msg, &signature)
+                    .map_err(|_| VerifyErr::BadSignature)
+            }
+        }
+    }
+}
+
+/// Public keys known to this instance indexed by key id
+#[derive(Default)]
+pub struct PublicKeyCache {
+    keys: HashMap<String, PublicKey>,
+}
+
+impl PublicKeyCache {
+    /// Load every `*.pem` (SPKI) public key from `dir`. The file stem is used as key id.
+    pub fn from_dir(dir: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let dir = dir.as_ref();
+        let mut keys = HashMap::new();
+
+        for entry in std::fs::read_dir(dir)
+            .with_context(|| format!("failed to read public keys dir {}", dir.display()))?
+        {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("pem") {
+                continue;
+            }
+
+            let key_id = match path.file_stem().and_then(|stem| stem.to_str()) {
+                Some(key_id) => key_id.to_string(),
+                None => continue,
+            };
+            let pem = std::fs::read_to_string(&path)?;
+            let key = PublicKey::from_public_key_pem(&pem)
+                .with_context(|| format!("invalid public key {}", path.display()))?;
+
+            tracing::info!("loaded public key `{}`", key_id);
+            keys.insert(key_id, key);
+        }
+
+        Ok(Self { keys })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Verify base64 encoded `signature` of `msg` made with key `key_id`
+    pub fn verify(&self, key_id: &str, msg: &[u8], signature: &str) -> Result<(), VerifyErr> {
+        let key = self
+            .keys
+            .get(key_id)
+            .ok_or_else(|| VerifyErr::UnknownKey(key_id.to_string()))?;
+        let signature = base64::decode(signature).map_err(|_| VerifyErr::MalformedBase64)?;
+        key.verify(msg, &signature)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VerifyReq {
+    pub message: String,
+    #[serde(default)]
+    pub encoding: MessageEncoding,
+    /// base64 encoded signature
+    pub signature: String,
+    pub key_id: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VerifyResp {
+    pub valid: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl From<Result<(), VerifyErr>> for VerifyResp {
+    fn from(res: Result<(), VerifyErr>) -> Self {
+        match res {
+            Ok(()) => Self {
+                valid: true,
+                reason: None,
+            },
+            Err(err) => Self {
+                valid: false,
+                reason: Some(err.to_string()),
+            },
+        }
+    }
+}
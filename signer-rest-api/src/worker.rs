@@ -1,176 +1,488 @@
-//! Heart of dealing with kafka signing
+//! Heart of dealing with sign requests, independent of `Transport` used to reach signer
 
-use rdkafka::{
-    config::RDKafkaLogLevel,
-    consumer::{Consumer, StreamConsumer},
-    error::KafkaError,
-    ClientConfig,
-};
+use futures::stream::FuturesUnordered;
+use signer_protocol::{ErrorCode, PayloadCodec, TraceContext};
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 use tokio::{
     select,
     sync::{
         mpsc::{self, Receiver, Sender},
-        oneshot,
+        oneshot, watch,
     },
+    time::Instant,
 };
 use tokio_stream::wrappers::ReceiverStream;
 use tokio_stream::StreamExt;
+use tokio_util::time::{delay_queue, DelayQueue};
+
+use crate::config::KafkaConfig;
+use crate::metrics::Metrics;
+use crate::signed_topic_consumer::{ConsumeErrSource, TopicConsumeErr};
+use crate::supervisor::{
+    Health, ProbeSlot, RestartPolicy, ShutdownHandle, Supervisor, TransportFailure, WorkerState,
+};
+use crate::transport::{Channels, KafkaTransport, Transport};
+use crate::{MsgResp, MsgSigned, MsgToSign};
+use signer_protocol::telemetry;
+
+/// How long `SignRequester` waits for signer by default
+pub const DEFAULT_SIGN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Request couldn't be signed
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum SignErr {
+    /// Signer didn't respond before deadline of the request
+    #[error("no response from signer before deadline")]
+    Timeout,
+    /// Signer answered with failure response
+    #[error("signer rejected request ({code}): {message}")]
+    Rejected { code: ErrorCode, message: String },
+    /// Transport failed and is being restarted, see [`Supervisor`]
+    #[error("transport to signer is down")]
+    Unavailable,
+    #[error(transparent)]
+    Transport(#[from] TopicConsumeErr),
+}
 
-use crate::signed_topic_consumer::TopicConsumeErr;
-use crate::{sign_producer::SignProducer, signed_topic_consumer::new_signed_topic_consumer};
-use crate::{MsgSigned, MsgToSign};
+impl SignErr {
+    /// `result` label of metrics
+    fn label(&self) -> &'static str {
+        match self {
+            SignErr::Timeout => "timeout",
+            SignErr::Rejected { .. } => "rejected",
+            SignErr::Unavailable => "unavailable",
+            SignErr::Transport(_) => "transport",
+        }
+    }
+}
 
-type SignPromiseItem = Result<MsgSigned, TopicConsumeErr>;
-type SignPromiseTx = oneshot::Sender<SignPromiseItem>;
+type SignPromiseItem = Result<MsgSigned, SignErr>;
 /// Promise that in some in futre we will receive signed message or error
-type SignPromiseRx = oneshot::Receiver<SignPromiseItem>;
+///
+/// `Worker` resolves it with `SignErr::Timeout` after deadline of the request.
+pub(crate) type SignPromiseRx = oneshot::Receiver<SignPromiseItem>;
+
+/// Sending side of promise, its resolution is recorded in [`Metrics`]
+///
+/// `span` of request is closed when promise is resolved.
+struct SignPromiseTx {
+    tx: oneshot::Sender<SignPromiseItem>,
+    started: Instant,
+    span: tracing::Span,
+}
+
+impl SignPromiseTx {
+    fn resolve(self, metrics: &Metrics, item: SignPromiseItem) {
+        let label = item.as_ref().map_or_else(SignErr::label, |_| "ok");
+        metrics.requests.with_label_values(&[label]).inc();
+        self.span.record("result", label);
+        metrics
+            .latency
+            .observe(self.started.elapsed().as_secs_f64());
+        if let Err(_nobody_wanted_resp) = self.tx.send(item) {
+            //TODO: rx was dropped nobody want our response
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct SignRequester {
     resp_topic: String,
-    inner: Sender<(MsgToSign, SignPromiseTx)>,
+    resp_partition: Option<i32>,
+    timeout: Duration,
+    pending: Arc<AtomicUsize>,
+    health: Health,
+    metrics: Metrics,
+    inner: Sender<(MsgToSign, Instant, SignPromiseTx)>,
 }
 
 impl SignRequester {
-    pub async fn start_req(&self, msg: String) -> Result<SignPromiseRx, ()> {
-        let req = MsgToSign::new(msg, self.resp_topic.clone());
+    /// Requests started by returned requester wait for signer at most `timeout`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Fails right away when transport is down
+    pub async fn start_req(&self, msg: Vec<u8>) -> Result<SignPromiseRx, ()> {
+        if !self.health.is_available() {
+            self.metrics
+                .requests
+                .with_label_values(&[SignErr::Unavailable.label()])
+                .inc();
+            return Err(());
+        }
+        // signer is told about deadline so it doesn't sign requests nobody waits for
+        let mut req = MsgToSign::new(msg, self.resp_topic.clone())
+            .with_deadline(SystemTime::now() + self.timeout);
+        if let Some(resp_partition) = self.resp_partition {
+            req = req.with_resp_partition(resp_partition);
+        }
+        // signer continues trace of this span
+        let span = tracing::info_span!(
+            "sign_request",
+            msg_id = %req.msg_id(),
+            result = tracing::field::Empty
+        );
+        let req = req.with_trace_context(telemetry::inject(&span));
+        let started = Instant::now();
+        let deadline = started + self.timeout;
         let (tx, rx) = oneshot::channel();
-        self.inner.send((req, tx)).await.map_err(drop)?;
+        let tx = SignPromiseTx { tx, started, span };
+        self.inner.send((req, deadline, tx)).await.map_err(drop)?;
         Ok(rx)
     }
+
+    /// Number of requests waiting for response in `Worker`
+    pub fn pending_reqs(&self) -> usize {
+        self.pending.load(Ordering::Relaxed)
+    }
+
+    pub fn health(&self) -> &Health {
+        &self.health
+    }
+
+    /// Metrics of `Worker` serving this requester
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+}
+
+/// Requests waiting for response, each at most until its deadline
+struct WaitingReqs {
+    promises: HashMap<String, (SignPromiseTx, delay_queue::Key)>,
+    deadlines: DelayQueue<String>,
+    pending: Arc<AtomicUsize>,
+    metrics: Metrics,
+}
+
+impl WaitingReqs {
+    fn insert(&mut self, msg_id: String, deadline: Instant, tx: SignPromiseTx) {
+        let key = self.deadlines.insert_at(msg_id.clone(), deadline);
+        let old = self.promises.insert(msg_id, (tx, key));
+        assert!(old.is_none());
+        self.update_pending();
+    }
+
+    /// Resolve promise with response (or error) from transport
+    fn send_resp(&mut self, resp: Result<MsgResp, TopicConsumeErr>) {
+        match resp {
+            Ok(MsgResp::Signed(resp)) => {
+                let _span = resp_span(resp.msg_id(), resp.trace_context()).entered();
+                let msg_id = resp.msg_id().to_owned();
+                self.send_resp_impl(&msg_id, Ok(resp))
+            }
+            Ok(MsgResp::Failed(failed)) => {
+                let _span = resp_span(failed.msg_id(), failed.trace_context()).entered();
+                let err = match failed.code() {
+                    // signer skipped request after its deadline
+                    ErrorCode::Expired => SignErr::Timeout,
+                    code => SignErr::Rejected {
+                        code,
+                        message: failed.message().to_string(),
+                    },
+                };
+                self.send_resp_impl(failed.msg_id(), Err(err))
+            }
+            Err(err) => {
+                // requests which couldn't be produced are looped back with their msg_id
+                if err.msg_id().is_some() && matches!(err.source_err(), ConsumeErrSource::Kafka(_))
+                {
+                    self.metrics.produce_errors.inc();
+                }
+                if let Some(msg_id) = err.msg_id() {
+                    let msg_id = msg_id.to_owned();
+                    self.send_resp_impl(&msg_id, Err(err.into()))
+                }
+            }
+        }
+    }
+
+    fn send_resp_impl(&mut self, msg_id: &str, resp: SignPromiseItem) {
+        if let Some((tx, key)) = self.promises.remove(msg_id) {
+            self.deadlines.remove(&key);
+            self.update_pending();
+            tx.resolve(&self.metrics, resp);
+        } else {
+            tracing::debug!(
+                "dropping response to unknown or expired request `{}`",
+                msg_id
+            );
+        }
+    }
+
+    /// Resolve promise of request which deadline passed
+    fn expire(&mut self, msg_id: &str) {
+        if let Some((tx, _key)) = self.promises.remove(msg_id) {
+            self.update_pending();
+            tx.resolve(&self.metrics, Err(SignErr::Timeout));
+        }
+    }
+
+    /// Resolve promises of all requests with `err`
+    fn fail_all(&mut self, err: SignErr) {
+        for (_, (tx, _key)) in self.promises.drain() {
+            tx.resolve(&self.metrics, Err(err.clone()));
+        }
+        self.deadlines.clear();
+        self.update_pending();
+    }
+
+    fn update_pending(&self) {
+        self.pending.store(self.promises.len(), Ordering::Relaxed);
+        self.metrics.waiting_reqs.set(self.promises.len() as i64);
+    }
+}
+
+/// Span of handling response, child of signer span which sent it
+fn resp_span(msg_id: &str, trace_context: &TraceContext) -> tracing::Span {
+    let span = tracing::info_span!("sign_response", msg_id);
+    telemetry::set_parent(&span, trace_context);
+    span
 }
 
 pub struct Worker {
-    request_stream: ReceiverStream<(MsgToSign, SignPromiseTx)>,
-    consumer: StreamConsumer,
-    waiting_reqs: HashMap<String, SignPromiseTx>,
+    request_stream: ReceiverStream<(MsgToSign, Instant, SignPromiseTx)>,
+    waiting_reqs: WaitingReqs,
+    state: watch::Sender<WorkerState>,
+    probe: ProbeSlot,
+    /// Drain timeout sent by [`ShutdownHandle`]
+    shutdown: (Sender<Duration>, Receiver<Duration>),
 }
 
 impl Worker {
-    /// Spawn worker and return a `SignRequester` to create sign requests
+    /// Spawn worker talking to signer through Kafka and return a `SignRequester` to create sign requests
     ///
-    /// `req_topic` -- is producer topic. One topic for as many application as you wish
-    /// `resp_topic` -- consumer. must be used only by one instance of application
+    /// See [`KafkaTransport::new`] for arguments. Transport is recreated after failure according to
+    /// default [`RestartPolicy`].
     pub fn spawn_new(
         req_topic: &str,
         resp_topic: &str,
-        brokers: &str,
-    ) -> Result<SignRequester, KafkaError> {
-        let (loopback_err_tx, loopback_err_rx) = mpsc::channel(1024);
-
-        let producer = ClientConfig::new()
-            .set("bootstrap.servers", brokers)
-            .set("message.timeout.ms", "5000")
-            .create()?;
+        config: &KafkaConfig,
+        codec: PayloadCodec,
+    ) -> (SignRequester, Supervisor) {
+        let (req_topic, resp_topic, config) = (
+            req_topic.to_string(),
+            resp_topic.to_string(),
+            config.clone(),
+        );
+        Self::spawn_supervised(
+            resp_topic.clone(),
+            None,
+            move || KafkaTransport::new(&req_topic, &resp_topic, &config, codec.clone()),
+            RestartPolicy::default(),
+        )
+    }
 
-        let (sing_producer, sender) = SignProducer::new(req_topic, producer, loopback_err_tx);
+    /// Spawn worker using `transport` and return a `SignRequester` to create sign requests
+    ///
+    /// Requests wait for response at most [`DEFAULT_SIGN_TIMEOUT`], see [`SignRequester::with_timeout`].
+    /// `transport` can't be recreated, so worker stops when it fails.
+    pub fn spawn<T: Transport + Send + 'static>(transport: T) -> (SignRequester, Supervisor) {
+        let resp_topic = transport.resp_topic().to_string();
+        let resp_partition = transport.resp_partition();
+        let mut transport = Some(transport);
+        Self::spawn_supervised(
+            resp_topic,
+            resp_partition,
+            move || transport.take().ok_or("transport can't be restarted"),
+            RestartPolicy::never(),
+        )
+    }
 
+    /// Spawn worker using transports created by `new_transport`, a new one after each failure
+    ///
+    /// Every transport must use `resp_topic` and `resp_partition`.
+    pub fn spawn_supervised<T, E, F>(
+        resp_topic: String,
+        resp_partition: Option<i32>,
+        new_transport: F,
+        policy: RestartPolicy,
+    ) -> (SignRequester, Supervisor)
+    where
+        T: Transport + 'static,
+        E: Into<Box<dyn std::error::Error + Send + Sync>> + 'static,
+        F: FnMut() -> Result<T, E> + Send + 'static,
+    {
         let (req_tx, req_rx) = mpsc::channel(1024);
-
-        let consumer: StreamConsumer = ClientConfig::new()
-            .set("group.id", "test.group.id")
-            .set("bootstrap.servers", brokers)
-            .set("enable.partition.eof", "false")
-            .set("session.timeout.ms", "6000")
-            .set("enable.auto.commit", "true")
-            .set_log_level(RDKafkaLogLevel::Debug)
-            .create()?;
-
-        consumer.subscribe(&[resp_topic])?;
+        let pending = Arc::new(AtomicUsize::new(0));
+        let (state, _) = watch::channel(WorkerState::Starting);
+        let metrics = Metrics::default();
 
         let worker = Self {
             request_stream: ReceiverStream::new(req_rx),
-            consumer,
-            waiting_reqs: HashMap::with_capacity(2048),
+            waiting_reqs: WaitingReqs {
+                promises: HashMap::with_capacity(2048),
+                deadlines: DelayQueue::with_capacity(2048),
+                pending: pending.clone(),
+                metrics: metrics.clone(),
+            },
+            state,
+            probe: ProbeSlot::default(),
+            shutdown: mpsc::channel(1),
         };
+        let health = worker.health();
+        let supervisor = Supervisor::spawn(worker, new_transport, policy);
 
-        //spawn producer and consumer tasks
-        // TODO: handle error of workers
-
-        tokio::spawn(async move {
-            worker
-                .work(loopback_err_rx, sender)
-                .await
-                .expect("expect to work");
-        });
+        let requester = SignRequester {
+            inner: req_tx,
+            resp_topic,
+            resp_partition,
+            timeout: DEFAULT_SIGN_TIMEOUT,
+            pending,
+            health,
+            metrics,
+        };
+        (requester, supervisor)
+    }
 
-        tokio::spawn(async move {
-            sing_producer.worker().await;
-        });
+    pub(crate) fn health(&self) -> Health {
+        Health::new(self.state.subscribe(), Arc::clone(&self.probe))
+    }
 
-        Ok(SignRequester {
-            inner: req_tx,
-            resp_topic: resp_topic.to_string(),
-        })
+    pub(crate) fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle(self.shutdown.0.clone())
     }
 
-    async fn work(
-        mut self,
-        sending_err: Receiver<TopicConsumeErr>,
-        producer: Sender<MsgToSign>,
-    ) -> Result<(), ()> {
-        let consumer_stream = self.consumer.stream();
+    pub(crate) fn set_state(&self, state: WorkerState) {
+        let _ = self.state.send(state);
+    }
 
-        let mut singed_msgs =
-            new_signed_topic_consumer(consumer_stream, ReceiverStream::new(sending_err));
+    /// Resolve promises of all waiting requests with `err`
+    pub(crate) fn fail_waiting(&mut self, err: SignErr) {
+        self.waiting_reqs.fail_all(err);
+    }
 
+    /// Reject requests coming until `deadline`. Returns `false` when all requesters are gone or
+    /// worker is shut down.
+    pub(crate) async fn reject_reqs_until(&mut self, deadline: Instant) -> bool {
+        let sleep = tokio::time::sleep_until(deadline);
+        tokio::pin!(sleep);
         loop {
+            select! {
+                _ = &mut sleep => return true,
+                new_req = self.request_stream.next() => match new_req {
+                    Some((_, _, tx)) => tx.resolve(&self.waiting_reqs.metrics, Err(SignErr::Unavailable)),
+                    None => return false,
+                },
+                Some(_) = self.shutdown.1.recv() => return false,
+            }
+        }
+    }
+
+    /// Pass requests and responses through `channels` until transport fails. Returns `Ok` when
+    /// all requesters are gone or worker was shut down, then transport is flushed.
+    pub(crate) async fn work(&mut self, channels: Channels) -> Result<(), TransportFailure> {
+        let Channels {
+            requests: producer,
+            responses,
+            tasks,
+            probe,
+            flush,
+        } = channels;
+        *self.probe.lock().unwrap() = probe;
+        let mut singed_msgs = ReceiverStream::new(responses);
+        let mut tasks: FuturesUnordered<_> = tasks.into_iter().collect();
+
+        let result = loop {
             select! {
                 new_req = self.request_stream.next() => match new_req {
-                    Some((msg_req, here_resp_will_be_send_when_ready)) => {
+                    Some((msg_req, deadline, here_resp_will_be_send_when_ready)) => {
 
                         let msg_id = msg_req.msg_id().to_string();
-                        match producer.send(msg_req).await {
-                            Ok(_) => (),
-                            Err(_err) => todo!("sing producer dropped"), // TODO: handle droping sing_producer
+                        if producer.send(msg_req).await.is_err() {
+                            here_resp_will_be_send_when_ready.resolve(&self.waiting_reqs.metrics, Err(SignErr::Unavailable));
+                            break Err(TransportFailure::Closed);
                         }
 
                         // wait for response
-                        let old = self.waiting_reqs.insert(msg_id, here_resp_will_be_send_when_ready);
-                        assert!(old.is_none());
+                        self.waiting_reqs.insert(msg_id, deadline, here_resp_will_be_send_when_ready);
                     },
-                    None => todo!(), // TODO: no more request will be provided we should run some teardown function
+                    None => break Ok(()),
                 },
                 new_res = singed_msgs.next() => match new_res {
                     Some(resp) => {
-                        send_resp(&mut self.waiting_reqs, resp)
+                        self.waiting_reqs.send_resp(resp)
                     },
-                    None => todo!(), // TODO: consumer disconnected. This could be probably rerunned
+                    None => break Err(TransportFailure::Closed),
+                },
+                Some(expired) = self.waiting_reqs.deadlines.next(), if !self.waiting_reqs.deadlines.is_empty() => {
+                    self.waiting_reqs.expire(expired.get_ref());
+                }
+                Some(stopped) = tasks.next(), if !tasks.is_empty() => {
+                    break Err(match stopped {
+                        Err(err) if err.is_panic() => TransportFailure::Panicked,
+                        _ => TransportFailure::Stopped,
+                    });
+                }
+                Some(drain_timeout) = self.shutdown.1.recv() => {
+                    self.drain(&mut singed_msgs, Instant::now() + drain_timeout).await;
+                    break Ok(());
                 }
             }
+        };
+
+        // requests already passed to transport are delivered before it's dropped
+        drop(producer);
+        if let (Ok(()), Some(flush)) = (&result, flush) {
+            flush.await;
         }
+        for task in tasks.iter() {
+            task.abort();
+        }
+        self.probe.lock().unwrap().take();
+        result
     }
 }
 
-fn send_resp(
-    waiting_reqs: &mut HashMap<String, SignPromiseTx>,
-    resp: Result<MsgSigned, TopicConsumeErr>,
-) {
-    match resp {
-        Ok(resp) => {
-            let msg_id = resp.msg_id().to_owned();
-            send_resp_impl(waiting_reqs, &msg_id, Ok(resp))
-        }
-        Err(err) => {
-            if let Some(msg_id) = err.msg_id() {
-                let msg_id = msg_id.to_owned();
-                send_resp_impl(waiting_reqs, &msg_id, Err(err))
+impl Worker {
+    /// Reject new requests and answer waiting ones until all requesters are gone and nothing is
+    /// waiting, at most until `deadline`
+    async fn drain(
+        &mut self,
+        singed_msgs: &mut ReceiverStream<Result<MsgResp, TopicConsumeErr>>,
+        deadline: Instant,
+    ) {
+        self.set_state(WorkerState::Draining);
+        tracing::info!(
+            "shutting down, waiting for {} requests",
+            self.waiting_reqs.promises.len()
+        );
+        let sleep = tokio::time::sleep_until(deadline);
+        tokio::pin!(sleep);
+        let mut requesters_gone = false;
+        while !(requesters_gone && self.waiting_reqs.promises.is_empty()) {
+            select! {
+                _ = &mut sleep => {
+                    tracing::warn!(
+                        "drain timeout passed, failing {} waiting requests",
+                        self.waiting_reqs.promises.len()
+                    );
+                    break;
+                },
+                new_req = self.request_stream.next(), if !requesters_gone => match new_req {
+                    Some((_, _, tx)) => tx.resolve(&self.waiting_reqs.metrics, Err(SignErr::Unavailable)),
+                    None => requesters_gone = true,
+                },
+                new_res = singed_msgs.next() => match new_res {
+                    Some(resp) => self.waiting_reqs.send_resp(resp),
+                    None => break,
+                },
+                Some(expired) = self.waiting_reqs.deadlines.next(), if !self.waiting_reqs.deadlines.is_empty() => {
+                    self.waiting_reqs.expire(expired.get_ref());
+                }
             }
         }
+        self.waiting_reqs.fail_all(SignErr::Unavailable);
     }
 }
 
-fn send_resp_impl(
-    waiting_reqs: &mut HashMap<String, SignPromiseTx>,
-    msg_id: &str,
-    resp: Result<MsgSigned, TopicConsumeErr>,
-) {
-    if let Some(tx) = waiting_reqs.remove(msg_id) {
-        if let Err(_nobody_wanted_resp) = tx.send(resp) {
-            //TODO: rx was dropped nobody want our response
-        }
-    } else {
-        //TODO: log dropeped response
+impl Drop for Worker {
+    fn drop(&mut self) {
+        // also when supervisor task panicked
+        self.set_state(WorkerState::Stopped);
     }
 }
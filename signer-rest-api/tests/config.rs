@@ -0,0 +1,83 @@
+use std::path::PathBuf;
+
+use signer_rest_api::config::{ConfigError, KafkaConfig};
+
+fn properties_file(test_name: &str, content: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!(
+        "signer-rest-api-{}-{}.properties",
+        test_name,
+        std::process::id()
+    ));
+    std::fs::write(&path, content).unwrap();
+    path
+}
+
+#[test]
+fn group_id_is_derived_from_resp_topic() {
+    let config = KafkaConfig::default();
+    assert_eq!(
+        config.group_id("signer.v1.resp.pod-0", None),
+        "signer-rest-api.signer.v1.resp.pod-0"
+    );
+    assert_eq!(
+        config.group_id("signer.v1.resp", Some(3)),
+        "signer-rest-api.signer.v1.resp.3"
+    );
+
+    let consumer = config.consumer_config("signer.v1.resp.pod-1", None);
+    assert_eq!(
+        consumer.get("group.id"),
+        Some("signer-rest-api.signer.v1.resp.pod-1")
+    );
+
+    let config = KafkaConfig {
+        group_id: Some("shared".to_string()),
+        ..KafkaConfig::default()
+    };
+    assert_eq!(config.group_id("signer.v1.resp", Some(3)), "shared");
+}
+
+#[test]
+fn properties_file_overrides_defaults() {
+    let path = properties_file(
+        "overrides",
+        "# cluster\n\
+         bootstrap.servers = kafka:9092\n\
+         security.protocol=SASL_SSL\n\
+         \n\
+         session.timeout.ms=10000\n\
+         consumer.fetch.wait.max.ms=50\n\
+         producer.linger.ms=1\n",
+    );
+    let config = KafkaConfig::default().with_properties_file(&path).unwrap();
+
+    assert_eq!(config.brokers, "kafka:9092");
+    assert_eq!(config.session_timeout_ms, 10000);
+
+    let consumer = config.consumer_config("signer.v1.resp.pod-0", None);
+    assert_eq!(consumer.get("bootstrap.servers"), Some("kafka:9092"));
+    assert_eq!(consumer.get("security.protocol"), Some("SASL_SSL"));
+    assert_eq!(consumer.get("session.timeout.ms"), Some("10000"));
+    assert_eq!(consumer.get("fetch.wait.max.ms"), Some("50"));
+    assert_eq!(consumer.get("linger.ms"), None);
+
+    let producer = config.producer_config();
+    assert_eq!(producer.get("security.protocol"), Some("SASL_SSL"));
+    assert_eq!(producer.get("linger.ms"), Some("1"));
+    assert_eq!(producer.get("fetch.wait.max.ms"), None);
+}
+
+#[test]
+fn invalid_properties_are_rejected() {
+    let path = properties_file("invalid-line", "bootstrap.servers=kafka:9092\nlinger.ms\n");
+    let err = KafkaConfig::default()
+        .with_properties_file(&path)
+        .unwrap_err();
+    assert!(matches!(err, ConfigError::InvalidLine(_, 2)));
+
+    let path = properties_file("invalid-value", "session.timeout.ms=soon\n");
+    let err = KafkaConfig::default()
+        .with_properties_file(&path)
+        .unwrap_err();
+    assert!(matches!(err, ConfigError::InvalidValue(key, _) if key == "session.timeout.ms"));
+}
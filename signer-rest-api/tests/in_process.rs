@@ -0,0 +1,149 @@
+mod common;
+
+use std::sync::Arc;
+
+use axum::body::Body;
+use axum::http::{header, Request, StatusCode};
+use axum::Router;
+use signer_rest_api::rest::{self, RouterConfig};
+use signer_rest_api::transport::InProcessTransport;
+use signer_rest_api::verify::PublicKeyCache;
+use signer_rest_api::{Worker, WorkerState};
+use tokio::sync::mpsc;
+use tower::ServiceExt;
+
+/// REST API and signer connected through channels
+fn in_process_router(test_name: &str) -> Router {
+    let (requester, _supervisor) = Worker::spawn(common::signer_transport());
+
+    let keys_dir = common::keys_dir(test_name);
+    let keys = PublicKeyCache::from_dir(&keys_dir).unwrap();
+    std::fs::remove_dir_all(&keys_dir).unwrap();
+
+    rest::router(requester, Arc::new(keys), RouterConfig::default())
+}
+
+async fn post_json(
+    router: &Router,
+    uri: &str,
+    body: serde_json::Value,
+) -> (StatusCode, serde_json::Value) {
+    let req = Request::post(uri)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    let resp = router.clone().oneshot(req).await.unwrap();
+    let status = resp.status();
+    let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+#[tokio::test]
+async fn sign_and_verify() {
+    let router = in_process_router("sign_and_verify");
+
+    let (status, signed) = post_json(
+        &router,
+        "/v1/sign",
+        serde_json::json!({ "message": "hello" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(signed["key_id"], "test-key");
+
+    let (status, verified) = post_json(
+        &router,
+        "/verify",
+        serde_json::json!({
+            "message": "hello",
+            "signature": signed["signature"],
+            "key_id": signed["key_id"],
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(verified, serde_json::json!({ "valid": true }));
+}
+
+#[tokio::test]
+async fn concurrent_requests_get_own_responses() {
+    let router = in_process_router("concurrent_requests");
+
+    let requests = (0..32).map(|i| {
+        let router = router.clone();
+        async move {
+            let message = format!("message {}", i);
+            let (status, signed) = post_json(
+                &router,
+                "/v1/sign",
+                serde_json::json!({ "message": message }),
+            )
+            .await;
+            assert_eq!(status, StatusCode::OK);
+            (message, signed)
+        }
+    });
+    let responses = futures::future::join_all(requests).await;
+
+    for (message, signed) in responses {
+        let (_, verified) = post_json(
+            &router,
+            "/verify",
+            serde_json::json!({
+                "message": message,
+                "signature": signed["signature"],
+                "key_id": "test-key",
+            }),
+        )
+        .await;
+        assert_eq!(verified["valid"], true, "signature of `{}`", message);
+    }
+}
+
+async fn get_json(router: &Router, uri: &str) -> (StatusCode, serde_json::Value) {
+    let req = Request::get(uri).body(Body::empty()).unwrap();
+    let resp = router.clone().oneshot(req).await.unwrap();
+    let status = resp.status();
+    let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+#[tokio::test]
+async fn probes_follow_worker_state() {
+    let (req_tx, _req_rx) = mpsc::channel(16);
+    let (resp_tx, resp_rx) = mpsc::channel(16);
+    let (requester, supervisor) =
+        Worker::spawn(InProcessTransport::new("in-process", req_tx, resp_rx));
+    let mut health = supervisor.health();
+    let router = rest::router(
+        requester,
+        Arc::new(PublicKeyCache::default()),
+        RouterConfig::default(),
+    );
+
+    assert_eq!(health.changed().await, WorkerState::Running);
+    let (status, body) = get_json(&router, "/readyz").await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let (status, _) = get_json(&router, "/healthz").await;
+    assert_eq!(status, StatusCode::OK);
+
+    let req = Request::get("/metrics").body(Body::empty()).unwrap();
+    let resp = router.clone().oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+    let text = String::from_utf8(body.to_vec()).unwrap();
+    assert!(
+        text.contains("signer_rest_api_waiting_requests 0"),
+        "{}",
+        text
+    );
+
+    // signer is gone, in-process transport can't be restarted
+    drop(resp_tx);
+    assert_eq!(health.changed().await, WorkerState::Stopped);
+    let (status, body) = get_json(&router, "/readyz").await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body["status"], "not_ready");
+    let (status, _) = get_json(&router, "/healthz").await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+}
@@ -0,0 +1,65 @@
+use std::time::Duration;
+
+use signer_rest_api::transport::InProcessTransport;
+use signer_rest_api::{MsgSigned, SignErr, Worker, WorkerState};
+use tokio::sync::mpsc;
+
+#[tokio::test]
+async fn waiting_requests_are_answered_during_shutdown() {
+    let (req_tx, mut req_rx) = mpsc::channel(16);
+    let (resp_tx, resp_rx) = mpsc::channel(16);
+    let (requester, supervisor) =
+        Worker::spawn(InProcessTransport::new("in-process", req_tx, resp_rx));
+    let mut health = supervisor.health();
+
+    let promise = requester.start_req(b"hello".to_vec()).await.unwrap();
+    let req = req_rx.recv().await.unwrap();
+
+    supervisor
+        .shutdown_handle()
+        .shutdown(Duration::from_secs(10));
+    while health.state() != WorkerState::Draining {
+        health.changed().await;
+    }
+    assert!(requester.start_req(b"late".to_vec()).await.is_err());
+
+    let signed = MsgSigned::new(
+        req.msg_id().to_string(),
+        "resp-1".to_string(),
+        "test-key".to_string(),
+        b"sig".to_vec(),
+    );
+    resp_tx.send(signed.clone().into()).await.unwrap();
+    assert_eq!(promise.await.unwrap().unwrap(), signed);
+
+    // worker stops once last requester is gone
+    drop(requester);
+    tokio::time::timeout(Duration::from_secs(1), supervisor.wait())
+        .await
+        .expect("worker stopped before drain timeout")
+        .unwrap();
+    assert_eq!(health.state(), WorkerState::Stopped);
+}
+
+#[tokio::test]
+async fn requests_fail_after_drain_timeout() {
+    // signer which never answers
+    let (req_tx, mut req_rx) = mpsc::channel(16);
+    let (_resp_tx, resp_rx) = mpsc::channel(16);
+    let (requester, supervisor) =
+        Worker::spawn(InProcessTransport::new("in-process", req_tx, resp_rx));
+
+    let promise = requester.start_req(b"hello".to_vec()).await.unwrap();
+    req_rx.recv().await.unwrap();
+
+    supervisor
+        .shutdown_handle()
+        .shutdown(Duration::from_millis(100));
+    assert!(matches!(promise.await.unwrap(), Err(SignErr::Unavailable)));
+    // requester is still alive, but drain is over
+    tokio::time::timeout(Duration::from_secs(1), supervisor.wait())
+        .await
+        .expect("worker stopped after drain timeout")
+        .unwrap();
+    assert_eq!(requester.health().state(), WorkerState::Stopped);
+}
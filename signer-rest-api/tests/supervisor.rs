@@ -0,0 +1,138 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use signer_rest_api::transport::{Channels, InProcessTransport, Transport};
+use signer_rest_api::{MsgResp, MsgToSign, RestartPolicy, SignErr, SupervisorError, Worker};
+use signer_rest_api::{TransportFailure, WorkerState};
+use tokio::sync::{mpsc, oneshot};
+
+/// In-process transport with additional task which panics when `crash` is sent
+struct CrashingTransport {
+    inner: InProcessTransport,
+    crash: oneshot::Receiver<()>,
+}
+
+impl Transport for CrashingTransport {
+    fn resp_topic(&self) -> &str {
+        self.inner.resp_topic()
+    }
+
+    fn start(self) -> Channels {
+        let mut channels = self.inner.start();
+        let crash = self.crash;
+        channels.tasks.push(tokio::spawn(async move {
+            if crash.await.is_ok() {
+                panic!("transport crashed");
+            }
+        }));
+        channels
+    }
+}
+
+/// Ends of channels of transports created by `Signer::new_transport`
+struct Signer {
+    requests: mpsc::Receiver<MsgToSign>,
+    _responses: mpsc::Sender<MsgResp>,
+    crash: oneshot::Sender<()>,
+}
+
+impl Signer {
+    fn new_transport() -> (CrashingTransport, Signer) {
+        let (req_tx, req_rx) = mpsc::channel(16);
+        let (resp_tx, resp_rx) = mpsc::channel(16);
+        let (crash_tx, crash_rx) = oneshot::channel();
+        let transport = CrashingTransport {
+            inner: InProcessTransport::new("in-process", req_tx, resp_rx),
+            crash: crash_rx,
+        };
+        let signer = Signer {
+            requests: req_rx,
+            _responses: resp_tx,
+            crash: crash_tx,
+        };
+        (transport, signer)
+    }
+}
+
+fn policy(backoff: Duration, max_failures: Option<u32>) -> RestartPolicy {
+    RestartPolicy {
+        min_backoff: backoff,
+        max_backoff: backoff,
+        max_failures,
+    }
+}
+
+#[tokio::test]
+async fn crashed_transport_is_restarted() {
+    let signers = Arc::new(Mutex::new(Vec::new()));
+    let created = Arc::clone(&signers);
+    let (requester, supervisor) = Worker::spawn_supervised(
+        "in-process".to_string(),
+        None,
+        move || {
+            let (transport, signer) = Signer::new_transport();
+            created.lock().unwrap().push(signer);
+            Ok::<_, TransportFailure>(transport)
+        },
+        policy(Duration::from_millis(200), Some(3)),
+    );
+    let mut health = supervisor.health();
+
+    let waiting = requester.start_req(b"first".to_vec()).await.unwrap();
+    assert_eq!(health.changed().await, WorkerState::Running);
+    let mut signer = signers.lock().unwrap().remove(0);
+    signer.requests.recv().await.unwrap();
+
+    signer.crash.send(()).unwrap();
+    assert!(matches!(waiting.await.unwrap(), Err(SignErr::Unavailable)));
+    assert_eq!(health.changed().await, WorkerState::Restarting);
+    // rejected right away while transport is down
+    assert!(requester.start_req(b"second".to_vec()).await.is_err());
+
+    assert_eq!(health.changed().await, WorkerState::Running);
+    let _promise = requester.start_req(b"third".to_vec()).await.unwrap();
+    let mut signer = signers.lock().unwrap().remove(0);
+    let req = signer.requests.recv().await.unwrap();
+    assert_eq!(req.msg(), b"third");
+}
+
+#[tokio::test]
+async fn supervisor_gives_up_after_max_failures() {
+    let (requester, supervisor) = Worker::spawn_supervised(
+        "in-process".to_string(),
+        None,
+        || Err::<CrashingTransport, _>("broker unavailable"),
+        policy(Duration::from_millis(10), Some(2)),
+    );
+    let health = supervisor.health();
+
+    match supervisor.wait().await {
+        Err(SupervisorError::GaveUp { failures, last }) => {
+            assert_eq!(failures, 3);
+            assert!(matches!(last, TransportFailure::Create(_)));
+        }
+        other => panic!("expected supervisor to give up, got {:?}", other),
+    }
+    assert_eq!(health.state(), WorkerState::Stopped);
+    assert!(requester.start_req(b"hello".to_vec()).await.is_err());
+}
+
+#[tokio::test]
+async fn transport_given_to_spawn_is_not_restarted() {
+    let (transport, mut signer) = Signer::new_transport();
+    let (requester, supervisor) = Worker::spawn(transport);
+
+    let promise = requester.start_req(b"hello".to_vec()).await.unwrap();
+    signer.requests.recv().await.unwrap();
+    signer.crash.send(()).unwrap();
+
+    assert!(matches!(promise.await.unwrap(), Err(SignErr::Unavailable)));
+    assert!(matches!(
+        supervisor.wait().await,
+        Err(SupervisorError::GaveUp {
+            last: TransportFailure::Panicked,
+            ..
+        })
+    ));
+    assert!(!requester.health().is_available());
+}
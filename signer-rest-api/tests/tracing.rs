@@ -0,0 +1,82 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use opentelemetry::sdk::export::trace::SpanData;
+use opentelemetry::sdk::trace::{Span, SpanProcessor, TracerProvider};
+use opentelemetry::trace::{SpanId, TraceResult, TracerProvider as _};
+use opentelemetry::Context;
+use signer_rest_api::Worker;
+use tracing::Instrument;
+use tracing_subscriber::prelude::*;
+
+/// Keeps finished spans
+#[derive(Debug, Clone, Default)]
+struct Finished(Arc<Mutex<Vec<SpanData>>>);
+
+impl Finished {
+    /// Wait until span `name` is finished
+    async fn span(&self, name: &str) -> SpanData {
+        for _ in 0..100 {
+            let finished = self.0.lock().unwrap().clone();
+            let found = finished.into_iter().find(|span| span.name == name);
+            if let Some(span) = found {
+                return span;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("span `{}` wasn't finished", name);
+    }
+}
+
+impl SpanProcessor for Finished {
+    fn on_start(&self, _span: &mut Span, _cx: &Context) {}
+
+    fn on_end(&self, span: SpanData) {
+        self.0.lock().unwrap().push(span);
+    }
+
+    fn force_flush(&self) -> TraceResult<()> {
+        Ok(())
+    }
+
+    fn shutdown(&mut self) -> TraceResult<()> {
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn round_trip_is_single_trace() {
+    let finished = Finished::default();
+    let provider = TracerProvider::builder()
+        .with_span_processor(finished.clone())
+        .build();
+    let subscriber = tracing_subscriber::registry()
+        .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+    // current thread runtime, so it's used also by spawned worker and signer
+    let _default = tracing::subscriber::set_default(subscriber);
+
+    let (requester, _supervisor) = Worker::spawn(common::signer_transport());
+
+    async {
+        let promise = requester.start_req(b"hello".to_vec()).await.unwrap();
+        promise.await.unwrap().unwrap();
+    }
+    .instrument(tracing::info_span!("client"))
+    .await;
+
+    let client = finished.span("client").await;
+    let request = finished.span("sign_request").await;
+    let sign = finished.span("sign").await;
+    let response = finished.span("sign_response").await;
+
+    let trace_id = client.span_context.trace_id();
+    for span in [&request, &sign, &response] {
+        assert_eq!(span.span_context.trace_id(), trace_id, "{}", span.name);
+    }
+    assert_eq!(client.parent_span_id, SpanId::INVALID);
+    assert_eq!(request.parent_span_id, client.span_context.span_id());
+    assert_eq!(sign.parent_span_id, request.span_context.span_id());
+    assert_eq!(response.parent_span_id, sign.span_context.span_id());
+}
@@ -0,0 +1,89 @@
+mod common;
+
+use common::{keys_dir, P256_PRIVATE_KEY, PRIVATE_KEY};
+use ed25519_dalek::pkcs8::DecodePrivateKey;
+use p256::ecdsa::signature::Signer;
+use signer_rest_api::verify::{PublicKeyCache, VerifyErr, VerifyResp};
+
+/// base64 Ed25519 signature of `msg` by [`PRIVATE_KEY`]
+fn sign_ed25519(msg: &[u8]) -> String {
+    let key = ed25519_dalek::SigningKey::from_pkcs8_pem(PRIVATE_KEY).unwrap();
+    base64::encode(key.sign(msg).to_bytes())
+}
+
+/// base64 DER ECDSA signature of `msg` by [`P256_PRIVATE_KEY`]
+fn sign_p256(msg: &[u8]) -> String {
+    let key = p256::ecdsa::SigningKey::from_pkcs8_pem(P256_PRIVATE_KEY).unwrap();
+    let signature: p256::ecdsa::Signature = key.sign(msg);
+    base64::encode(signature.to_der().as_bytes())
+}
+
+#[test]
+fn signatures_of_both_algorithms_are_verified() {
+    let keys = PublicKeyCache::from_dir(keys_dir("both_algorithms")).unwrap();
+
+    let signature = sign_ed25519(b"hello");
+    keys.verify("test-key", b"hello", &signature).unwrap();
+    assert!(matches!(
+        keys.verify("test-key", b"other", &signature),
+        Err(VerifyErr::BadSignature)
+    ));
+
+    let signature = sign_p256(b"hello");
+    keys.verify("p256-key", b"hello", &signature).unwrap();
+    assert!(matches!(
+        keys.verify("p256-key", b"other", &signature),
+        Err(VerifyErr::BadSignature)
+    ));
+    // signature made with other key
+    assert!(matches!(
+        keys.verify("test-key", b"hello", &signature),
+        Err(VerifyErr::MalformedSignature)
+    ));
+}
+
+#[test]
+fn only_pem_files_are_loaded() {
+    let keys = PublicKeyCache::from_dir(keys_dir("only_pem")).unwrap();
+    assert!(!keys.is_empty());
+    assert!(matches!(
+        keys.verify("README", b"hello", "AAAA"),
+        Err(VerifyErr::UnknownKey(key_id)) if key_id == "README"
+    ));
+}
+
+#[test]
+fn malformed_signatures_are_reported() {
+    let keys = PublicKeyCache::from_dir(keys_dir("malformed")).unwrap();
+
+    assert!(matches!(
+        keys.verify("test-key", b"hello", "not base64!"),
+        Err(VerifyErr::MalformedBase64)
+    ));
+    // valid base64, but too short for any algorithm
+    assert!(matches!(
+        keys.verify("test-key", b"hello", "AAAA"),
+        Err(VerifyErr::MalformedSignature)
+    ));
+    assert!(matches!(
+        keys.verify("p256-key", b"hello", "AAAA"),
+        Err(VerifyErr::MalformedSignature)
+    ));
+}
+
+#[test]
+fn failed_verification_has_reason() {
+    let keys = PublicKeyCache::from_dir(keys_dir("reason")).unwrap();
+
+    let resp = VerifyResp::from(keys.verify("missing", b"hello", "AAAA"));
+    assert_eq!(
+        serde_json::to_value(&resp).unwrap(),
+        serde_json::json!({ "valid": false, "reason": "unknown key id `missing`" })
+    );
+
+    let resp = VerifyResp::from(Ok(()));
+    assert_eq!(
+        serde_json::to_value(&resp).unwrap(),
+        serde_json::json!({ "valid": true })
+    );
+}
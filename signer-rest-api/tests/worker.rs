@@ -0,0 +1,140 @@
+use std::time::Duration;
+
+use signer_protocol::{ErrorCode, MsgFailed};
+use signer_rest_api::transport::{Channels, InProcessTransport, Transport};
+use signer_rest_api::{SignErr, Worker};
+use tokio::sync::mpsc;
+
+#[tokio::test]
+async fn unanswered_request_times_out() {
+    // signer which never answers
+    let (req_tx, mut req_rx) = mpsc::channel(16);
+    let (_resp_tx, resp_rx) = mpsc::channel(16);
+
+    let (requester, _supervisor) =
+        Worker::spawn(InProcessTransport::new("in-process", req_tx, resp_rx));
+    let requester = requester.with_timeout(Duration::from_millis(100));
+    assert_eq!(requester.pending_reqs(), 0);
+
+    let promise = requester.start_req(b"hello".to_vec()).await.unwrap();
+    req_rx.recv().await.expect("request reached signer");
+
+    let started = tokio::time::Instant::now();
+    let resp = promise.await.expect("promise resolved by worker");
+    assert!(matches!(resp, Err(SignErr::Timeout)));
+    assert!(started.elapsed() < Duration::from_secs(1));
+    assert_eq!(requester.pending_reqs(), 0);
+    let timeouts = requester.metrics().requests.with_label_values(&["timeout"]);
+    assert_eq!(timeouts.get(), 1);
+}
+
+#[tokio::test]
+async fn pending_requests_are_counted() {
+    let (req_tx, mut req_rx) = mpsc::channel(16);
+    let (resp_tx, resp_rx) = mpsc::channel(16);
+    let (requester, _supervisor) =
+        Worker::spawn(InProcessTransport::new("in-process", req_tx, resp_rx));
+
+    let first = requester.start_req(b"first".to_vec()).await.unwrap();
+    let _second = requester.start_req(b"second".to_vec()).await.unwrap();
+    let first_req = req_rx.recv().await.unwrap();
+    req_rx.recv().await.unwrap();
+    assert_eq!(requester.pending_reqs(), 2);
+
+    let signed = signer_rest_api::MsgSigned::new(
+        first_req.msg_id().to_string(),
+        "resp-1".to_string(),
+        "test-key".to_string(),
+        b"sig".to_vec(),
+    );
+    resp_tx.send(signed.clone().into()).await.unwrap();
+
+    assert_eq!(first.await.unwrap().unwrap(), signed);
+    assert_eq!(requester.pending_reqs(), 1);
+
+    let metrics = requester.metrics();
+    assert_eq!(metrics.waiting_reqs.get(), 1);
+    assert_eq!(metrics.requests.with_label_values(&["ok"]).get(), 1);
+    assert_eq!(metrics.latency.get_sample_count(), 1);
+}
+
+#[tokio::test]
+async fn requests_carry_deadline_and_expired_response_frees_them() {
+    let (req_tx, mut req_rx) = mpsc::channel(16);
+    let (resp_tx, resp_rx) = mpsc::channel(16);
+    let (requester, _supervisor) =
+        Worker::spawn(InProcessTransport::new("in-process", req_tx, resp_rx));
+    let requester = requester.with_timeout(Duration::from_secs(60));
+
+    let promise = requester.start_req(b"hello".to_vec()).await.unwrap();
+    let req = req_rx.recv().await.unwrap();
+    assert!(req.deadline().expect("deadline is set") > std::time::SystemTime::now());
+
+    let expired = MsgFailed::new(
+        req.msg_id().to_string(),
+        "resp-1".to_string(),
+        ErrorCode::Expired,
+        "deadline passed".to_string(),
+    );
+    resp_tx.send(expired.into()).await.unwrap();
+
+    assert!(matches!(promise.await.unwrap(), Err(SignErr::Timeout)));
+    assert_eq!(requester.pending_reqs(), 0);
+}
+
+#[tokio::test]
+async fn failure_response_is_distinguishable() {
+    let (req_tx, mut req_rx) = mpsc::channel(16);
+    let (resp_tx, resp_rx) = mpsc::channel(16);
+    let (requester, _supervisor) =
+        Worker::spawn(InProcessTransport::new("in-process", req_tx, resp_rx));
+
+    let promise = requester.start_req(b"hello".to_vec()).await.unwrap();
+    let req = req_rx.recv().await.unwrap();
+
+    let failed = MsgFailed::new(
+        req.msg_id().to_string(),
+        "resp-1".to_string(),
+        ErrorCode::SigningFailed,
+        "signer failed to produce signature".to_string(),
+    );
+    resp_tx.send(failed.into()).await.unwrap();
+
+    match promise.await.unwrap() {
+        Err(SignErr::Rejected { code, message }) => {
+            assert_eq!(code, ErrorCode::SigningFailed);
+            assert_eq!(message, "signer failed to produce signature");
+        }
+        other => panic!("expected rejection, got {:?}", other),
+    }
+}
+
+/// In-process transport pretending to consume single partition of shared response topic
+struct PartitionTransport(InProcessTransport);
+
+impl Transport for PartitionTransport {
+    fn resp_topic(&self) -> &str {
+        self.0.resp_topic()
+    }
+
+    fn resp_partition(&self) -> Option<i32> {
+        Some(7)
+    }
+
+    fn start(self) -> Channels {
+        self.0.start()
+    }
+}
+
+#[tokio::test]
+async fn requests_carry_resp_partition_of_transport() {
+    let (req_tx, mut req_rx) = mpsc::channel(16);
+    let (_resp_tx, resp_rx) = mpsc::channel(16);
+    let transport = PartitionTransport(InProcessTransport::new("signer.v1.resp", req_tx, resp_rx));
+    let (requester, _supervisor) = Worker::spawn(transport);
+
+    let _promise = requester.start_req(b"hello".to_vec()).await.unwrap();
+    let req = req_rx.recv().await.unwrap();
+    assert_eq!(req.resp_topic(), "signer.v1.resp");
+    assert_eq!(req.resp_partition(), Some(7));
+}
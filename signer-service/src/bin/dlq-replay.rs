@@ -0,0 +1,110 @@
+//! Re-inject dead-lettered sign requests into their source topic
+//!
+//! Reads DLQ topic from the last committed offset of `SIGNER_DLQ_REPLAY_GROUP_ID` group, publishes
+//! every record without `dlq_*` headers and exits after `SIGNER_DLQ_REPLAY_IDLE_TIMEOUT_MS` without
+//! new records. Offsets are committed only after record was republished, so interrupted replay
+//! can be run again.
+//!
+//! `deadline` of the original request is long past, so it's dropped and replayed requests never
+//! expire, unless `SIGNER_DLQ_REPLAY_DEADLINE_MS` gives them a new one.
+
+use rdkafka::config::RDKafkaLogLevel;
+use rdkafka::consumer::{CommitMode, Consumer, StreamConsumer};
+use rdkafka::message::OwnedHeaders;
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use rdkafka::{ClientConfig, Message};
+use signer_service::dlq;
+use std::env;
+use std::time::{Duration, SystemTime};
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::init();
+
+    let brokers = env::var("SIGNER_DLQ_REPLAY_KAFKA_BROKERS")
+        .unwrap_or_else(|_| "127.0.0.1:9092".to_string());
+    let dlq_topic =
+        env::var("SIGNER_DLQ_REPLAY_DLQ_TOPIC").unwrap_or_else(|_| "signer.v1.dlq".to_string());
+    let group_id = env::var("SIGNER_DLQ_REPLAY_GROUP_ID")
+        .unwrap_or_else(|_| "signer.v1.dlq-replay".to_string());
+    // by default records go back to topic they were dead-lettered from
+    let target_topic = env::var("SIGNER_DLQ_REPLAY_TARGET_TOPIC").ok();
+    let idle_timeout: u64 = env::var("SIGNER_DLQ_REPLAY_IDLE_TIMEOUT_MS")
+        .unwrap_or_else(|_| "10000".to_string())
+        .parse()?;
+    // time signer has to answer replayed request, counted from its replay
+    let deadline = match env::var("SIGNER_DLQ_REPLAY_DEADLINE_MS") {
+        Ok(deadline_ms) => Some(Duration::from_millis(deadline_ms.parse()?)),
+        Err(_) => None,
+    };
+
+    let producer: FutureProducer = ClientConfig::new()
+        .set("bootstrap.servers", &brokers)
+        .set("message.timeout.ms", "5000")
+        .create()?;
+
+    let consumer: StreamConsumer = ClientConfig::new()
+        .set("group.id", &group_id)
+        .set("bootstrap.servers", &brokers)
+        .set("enable.partition.eof", "false")
+        .set("enable.auto.commit", "false")
+        .set("auto.offset.reset", "earliest")
+        .set_log_level(RDKafkaLogLevel::Debug)
+        .create()?;
+    consumer.subscribe(&[&dlq_topic])?;
+
+    let mut replayed = 0;
+    let mut skipped = 0;
+    loop {
+        let msg = match tokio::time::timeout(Duration::from_millis(idle_timeout), consumer.recv())
+            .await
+        {
+            Ok(msg) => msg?,
+            Err(_elapsed) => break,
+        };
+
+        let topic = target_topic.as_deref().or_else(|| dlq::source_topic(&msg));
+        match topic {
+            Some(topic) => {
+                let deadline = deadline.map(|deadline| SystemTime::now() + deadline);
+                let headers = msg
+                    .headers()
+                    .map(|headers| dlq::replay_headers(headers, deadline))
+                    .unwrap_or_else(OwnedHeaders::new);
+                let mut record = FutureRecord::<[u8], [u8]>::to(topic).headers(headers);
+                if let Some(key) = msg.key() {
+                    record = record.key(key);
+                }
+                if let Some(payload) = msg.payload() {
+                    record = record.payload(payload);
+                }
+
+                producer
+                    .send(record, Duration::from_secs(5))
+                    .await
+                    .map_err(|(err, _ow_msg)| err)?;
+                replayed += 1;
+            }
+            None => {
+                tracing::warn!(
+                    "skipping record {}/{} without `{}` header",
+                    msg.partition(),
+                    msg.offset(),
+                    dlq::SOURCE_TOPIC
+                );
+                skipped += 1;
+            }
+        }
+
+        consumer.commit_message(&msg, CommitMode::Sync)?;
+    }
+
+    tracing::info!(
+        "replayed {} records from `{}`, skipped {}",
+        replayed,
+        dlq_topic,
+        skipped
+    );
+
+    Ok(())
+}
@@ -0,0 +1,78 @@
+//! Dead-letter topic for requests that can't be decoded
+//!
+//! Dead-lettered record keeps key, payload and headers of the original one. Where it was read
+//! from and why it was rejected is appended in `dlq_*` headers. `dlq-replay` binary strips them
+//! and publishes the record back to its source topic, see [`replay_headers`].
+
+use rdkafka::message::{Headers, OwnedHeaders};
+use rdkafka::Message;
+use signer_protocol::{headers, ProtocolError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const SOURCE_TOPIC: &str = "dlq_source_topic";
+pub const SOURCE_PARTITION: &str = "dlq_source_partition";
+pub const SOURCE_OFFSET: &str = "dlq_source_offset";
+/// Why request couldn't be decoded
+pub const ERROR: &str = "dlq_error";
+
+const DLQ_HEADERS: [&str; 4] = [SOURCE_TOPIC, SOURCE_PARTITION, SOURCE_OFFSET, ERROR];
+
+/// Headers of dead-lettered `msg`: original headers followed by `dlq_*` ones
+pub fn dead_letter_headers<M: Message>(msg: &M, err: &ProtocolError) -> OwnedHeaders {
+    let headers = match msg.headers() {
+        Some(headers) => copy_headers(headers, |_| true),
+        None => OwnedHeaders::new(),
+    };
+
+    headers
+        .add(SOURCE_TOPIC, msg.topic())
+        .add(SOURCE_PARTITION, &msg.partition().to_string())
+        .add(SOURCE_OFFSET, &msg.offset().to_string())
+        .add(ERROR, &err.to_string())
+}
+
+/// Headers of dead-lettered record as they were before it was dead-lettered
+pub fn strip_dead_letter_headers<H: Headers + ?Sized>(headers: &H) -> OwnedHeaders {
+    copy_headers(headers, |name| !DLQ_HEADERS.contains(&name))
+}
+
+/// Headers of replayed record: original ones with `deadline` replaced by `deadline`, or removed
+/// when it's `None`
+///
+/// Original deadline is long past when record is replayed, so signer would skip it as expired.
+pub fn replay_headers<H: Headers + ?Sized>(
+    dead_lettered: &H,
+    deadline: Option<SystemTime>,
+) -> OwnedHeaders {
+    let replayed = copy_headers(dead_lettered, |name| {
+        !DLQ_HEADERS.contains(&name) && name != headers::DEADLINE
+    });
+    match deadline {
+        Some(deadline) => {
+            let millis = deadline
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis();
+            replayed.add(headers::DEADLINE, &millis.to_string())
+        }
+        None => replayed,
+    }
+}
+
+/// Topic the dead-lettered `msg` was read from
+pub fn source_topic<M: Message>(msg: &M) -> Option<&str> {
+    let headers = msg.headers()?;
+    (0..headers.count())
+        .filter_map(|idx| headers.get(idx))
+        .find(|(name, _)| *name == SOURCE_TOPIC)
+        .and_then(|(_, value)| std::str::from_utf8(value).ok())
+}
+
+fn copy_headers<H: Headers + ?Sized>(headers: &H, keep: impl Fn(&str) -> bool) -> OwnedHeaders {
+    (0..headers.count())
+        .filter_map(|idx| headers.get(idx))
+        .filter(|(name, _)| keep(name))
+        .fold(OwnedHeaders::new(), |acc, (name, value)| {
+            acc.add(name, value)
+        })
+}
@@ -0,0 +1,135 @@
+//! HTTP server for k8s probes and Prometheus
+//!
+//! `/healthz` answers as long as the process runs. `/readyz` reports partitions assigned to
+//! request consumer with their lag and fails when none is assigned. `/metrics` serves
+//! [`Metrics`].
+
+use std::convert::Infallible;
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::time::Duration;
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use rdkafka::consumer::{Consumer, ConsumerContext, StreamConsumer};
+use rdkafka::error::KafkaError;
+use rdkafka::Offset;
+use serde::Serialize;
+
+use crate::metrics::Metrics;
+
+/// How long assignment report waits for broker
+const REPORT_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PartitionLag {
+    pub topic: String,
+    pub partition: i32,
+    /// Committed offset of consumer group, `None` before first commit
+    pub committed: Option<i64>,
+    pub high_watermark: i64,
+    /// Records not finished yet, `None` before first commit
+    pub lag: Option<i64>,
+}
+
+/// Source of assignment report. Called in blocking thread, may wait for broker.
+pub trait Assignment: Send + Sync + 'static {
+    fn assignment(&self) -> Result<Vec<PartitionLag>, KafkaError>;
+}
+
+impl<C: ConsumerContext + 'static> Assignment for StreamConsumer<C> {
+    fn assignment(&self) -> Result<Vec<PartitionLag>, KafkaError> {
+        let assignment = Consumer::assignment(self)?;
+        let committed = self.committed_offsets(assignment, REPORT_TIMEOUT)?;
+
+        committed
+            .elements()
+            .iter()
+            .map(|elem| {
+                let (_low, high_watermark) =
+                    self.fetch_watermarks(elem.topic(), elem.partition(), REPORT_TIMEOUT)?;
+                let committed = match elem.offset() {
+                    Offset::Offset(offset) => Some(offset),
+                    _ => None,
+                };
+                Ok(PartitionLag {
+                    topic: elem.topic().to_string(),
+                    partition: elem.partition(),
+                    committed,
+                    high_watermark,
+                    lag: committed.map(|committed| high_watermark - committed),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Serve probes and metrics on `listener` until the process exits
+pub async fn serve(
+    listener: TcpListener,
+    assignment: Arc<dyn Assignment>,
+    metrics: Metrics,
+) -> Result<(), hyper::Error> {
+    let make_service = make_service_fn(move |_conn| {
+        let assignment = Arc::clone(&assignment);
+        let metrics = metrics.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                let assignment = Arc::clone(&assignment);
+                let metrics = metrics.clone();
+                async move { Ok::<_, Infallible>(handle(req, assignment, metrics).await) }
+            }))
+        }
+    });
+
+    Server::from_tcp(listener)?.serve(make_service).await
+}
+
+async fn handle(
+    req: Request<Body>,
+    assignment: Arc<dyn Assignment>,
+    metrics: Metrics,
+) -> Response<Body> {
+    match (req.method(), req.uri().path()) {
+        (&Method::GET, "/healthz") => json(StatusCode::OK, serde_json::json!({ "status": "ok" })),
+        (&Method::GET, "/readyz") => readyz(assignment).await,
+        (&Method::GET, "/metrics") => Response::builder()
+            .header("content-type", signer_protocol::metrics::CONTENT_TYPE)
+            .body(Body::from(metrics.registry().encode()))
+            .expect("valid response"),
+        _ => json(
+            StatusCode::NOT_FOUND,
+            serde_json::json!({ "status": "not_found" }),
+        ),
+    }
+}
+
+async fn readyz(assignment: Arc<dyn Assignment>) -> Response<Body> {
+    let report = tokio::task::spawn_blocking(move || assignment.assignment()).await;
+    match report {
+        Ok(Ok(partitions)) if partitions.is_empty() => json(
+            StatusCode::SERVICE_UNAVAILABLE,
+            serde_json::json!({ "status": "not_ready", "reason": "no partition assigned" }),
+        ),
+        Ok(Ok(partitions)) => json(
+            StatusCode::OK,
+            serde_json::json!({ "status": "ok", "assignment": partitions }),
+        ),
+        Ok(Err(err)) => json(
+            StatusCode::SERVICE_UNAVAILABLE,
+            serde_json::json!({ "status": "not_ready", "reason": err.to_string() }),
+        ),
+        Err(_panicked) => json(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            serde_json::json!({ "status": "error" }),
+        ),
+    }
+}
+
+fn json(status: StatusCode, body: serde_json::Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .expect("valid response")
+}
@@ -0,0 +1,232 @@
+//! Service signing requested messages
+//!
+//! Requests are received and responses delivered through [`Transport`],
+//! normally Kafka.
+
+pub mod dlq;
+pub mod health;
+pub mod metrics;
+pub mod offsets;
+pub mod signer;
+pub mod transaction;
+pub mod transport;
+
+use futures::future::{self, Future};
+use futures::stream::{FuturesUnordered, StreamExt};
+use metrics::Metrics;
+use signer::{SignatureError, Signer};
+use signer_protocol::{telemetry, ErrorCode, MsgFailed, MsgResp, MsgSigned, MsgToSign};
+use std::num::NonZeroUsize;
+use std::time::Instant;
+use tracing::Instrument;
+use transport::{Incoming, InvalidReq, ReplyTo, Transport};
+
+/// Sign requested message with `signer`
+pub fn sign(msg_to_sign: MsgToSign, signer: &dyn Signer) -> Result<MsgSigned, SignatureError> {
+    Ok(MsgSigned::new(
+        msg_to_sign.msg_id().to_string(),
+        uuid::Uuid::new_v4().to_string(),
+        signer.key_id().to_string(),
+        signer.sign(msg_to_sign.msg())?,
+    ))
+}
+
+/// How many requests [`Service`] processes at once by default
+pub const DEFAULT_MAX_IN_FLIGHT: usize = 64;
+
+fn failure(msg_id: &str, code: ErrorCode, message: impl Into<String>) -> MsgResp {
+    MsgFailed::new(
+        msg_id.to_string(),
+        uuid::Uuid::new_v4().to_string(),
+        code,
+        message.into(),
+    )
+    .into()
+}
+
+pub struct Service {
+    signer: Box<dyn Signer>,
+    reply_expired: bool,
+    max_in_flight: usize,
+    metrics: Metrics,
+}
+
+impl Service {
+    pub fn new(signer: Box<dyn Signer>) -> Self {
+        Self {
+            signer,
+            reply_expired: false,
+            max_in_flight: DEFAULT_MAX_IN_FLIGHT,
+            metrics: Metrics::default(),
+        }
+    }
+
+    /// Answer requests which deadline passed with `expired` failure instead of ignoring them, so
+    /// requester can forget them right away
+    pub fn with_reply_expired(mut self, reply_expired: bool) -> Self {
+        self.reply_expired = reply_expired;
+        self
+    }
+
+    pub fn signer(&self) -> &dyn Signer {
+        self.signer.as_ref()
+    }
+
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// At most `max_in_flight` requests are signed and answered at once, [`DEFAULT_MAX_IN_FLIGHT`]
+    /// by default. Transport can lower it, see [`Transport::max_in_flight`].
+    pub fn with_max_in_flight(mut self, max_in_flight: NonZeroUsize) -> Self {
+        self.max_in_flight = max_in_flight.get();
+        self
+    }
+
+    /// Answer requests from `transport` until it's closed or fails
+    ///
+    /// Request is committed after its response was sent, so it's received again if service stops
+    /// before that. Requests are processed concurrently and responses can be sent in different
+    /// order than requests were received, it's the transport which commits them in order.
+    pub async fn run<T: Transport>(&self, transport: T) -> Result<(), T::Error> {
+        self.run_until(transport, future::pending()).await
+    }
+
+    /// Like [`Service::run`] but stops receiving requests when `shutdown` completes
+    ///
+    /// Requests already received are still answered and committed, then transport is closed, see
+    /// [`Transport::close`].
+    pub async fn run_until<T, F>(&self, transport: T, shutdown: F) -> Result<(), T::Error>
+    where
+        T: Transport,
+        F: Future<Output = ()>,
+    {
+        let max_in_flight = self.max_in_flight.min(transport.max_in_flight()).max(1);
+        let mut in_flight = FuturesUnordered::new();
+        // kept between iterations, receiving is not cancelled when some request is finished
+        let mut recv = transport.recv();
+        let mut closed = false;
+        tokio::pin!(shutdown);
+
+        loop {
+            tokio::select! {
+                () = &mut shutdown, if !closed => {
+                    tracing::info!("shutting down, finishing {} requests in flight", in_flight.len());
+                    closed = true;
+                },
+                incoming = &mut recv, if !closed && in_flight.len() < max_in_flight => match incoming {
+                    Some(incoming) => {
+                        let incoming = incoming?;
+                        let span = request_span(&incoming.0);
+                        in_flight.push(self.process(&transport, incoming).instrument(span));
+                        recv = transport.recv();
+                    }
+                    None => closed = true,
+                },
+                Some(processed) = in_flight.next() => processed?,
+                else => break,
+            }
+        }
+
+        transport.close().await
+    }
+
+    /// Answer single request and commit it
+    async fn process<T: Transport>(
+        &self,
+        transport: &T,
+        (incoming, receipt): (Incoming, T::Receipt),
+    ) -> Result<(), T::Error> {
+        self.metrics.consumed.inc();
+        let reply = match incoming {
+            Ok(msg_to_sign) => {
+                let reply_to = ReplyTo::of(&msg_to_sign);
+                self.handle(msg_to_sign).map(|resp| (reply_to, resp))
+            }
+            Err(invalid) => {
+                self.failed("invalid_request");
+                reject(invalid)
+            }
+        };
+
+        if let Some((reply_to, resp)) = reply {
+            // requester continues trace of request span
+            let resp = resp.with_trace_context(telemetry::inject(&tracing::Span::current()));
+            let status = match resp {
+                MsgResp::Signed(_) => "signed",
+                MsgResp::Failed(_) => "failed",
+            };
+            transport.send(&reply_to, resp).await?;
+            self.metrics.produced.with_label_values(&[status]).inc();
+        }
+        transport.commit(receipt).await
+    }
+
+    /// Sign request unless its deadline passed
+    fn handle(&self, msg_to_sign: MsgToSign) -> Option<MsgResp> {
+        if msg_to_sign.is_expired() {
+            self.failed("expired");
+            tracing::debug!("skipping expired request `{}`", msg_to_sign.msg_id());
+
+            return self.reply_expired.then(|| {
+                failure(
+                    msg_to_sign.msg_id(),
+                    ErrorCode::Expired,
+                    "deadline passed before request was signed",
+                )
+            });
+        }
+
+        let msg_id = msg_to_sign.msg_id().to_string();
+        let started = Instant::now();
+        let signed = sign(msg_to_sign, self.signer());
+        self.metrics
+            .sign_duration
+            .with_label_values(&[self.signer.key_id()])
+            .observe(started.elapsed().as_secs_f64());
+        match signed {
+            Ok(signed) => Some(signed.into()),
+            Err(err) => {
+                self.failed("signing_failed");
+                tracing::error!("failed to sign request `{}`: {}", msg_id, err);
+                Some(failure(
+                    &msg_id,
+                    ErrorCode::SigningFailed,
+                    "signer failed to produce signature",
+                ))
+            }
+        }
+    }
+
+    /// Count request which wasn't signed
+    fn failed(&self, reason: &str) {
+        self.metrics.failed.with_label_values(&[reason]).inc();
+    }
+}
+
+/// Span of processing request, child of requester span when request carries its trace context
+fn request_span(incoming: &Incoming) -> tracing::Span {
+    match incoming {
+        Ok(msg_to_sign) => {
+            let span = tracing::info_span!("sign", msg_id = %msg_to_sign.msg_id());
+            telemetry::set_parent(&span, msg_to_sign.trace_context());
+            span
+        }
+        Err(_) => tracing::info_span!("invalid_request"),
+    }
+}
+
+/// Answer invalid request with `invalid_request` failure when we know where to send it
+fn reject(invalid: InvalidReq) -> Option<(ReplyTo, MsgResp)> {
+    match invalid.reply_to {
+        Some((msg_id, reply_to)) => {
+            tracing::warn!("rejecting invalid request `{}`: {}", msg_id, invalid.err);
+            let resp = failure(&msg_id, ErrorCode::InvalidRequest, invalid.err.to_string());
+            Some((reply_to, resp))
+        }
+        None => {
+            tracing::error!("unexpected format of request: {:?}", invalid.err);
+            None
+        }
+    }
+}
@@ -1,87 +1,21 @@
-use anyhow::bail;
-use rdkafka::config::RDKafkaLogLevel;
-use rdkafka::consumer::{Consumer, StreamConsumer};
-use rdkafka::message::{BorrowedMessage, Headers, OwnedHeaders};
-use rdkafka::producer::{FutureProducer, FutureRecord};
-use rdkafka::{ClientConfig, Message};
+use signer_protocol::schema_registry::SchemaRegistry;
+use signer_protocol::telemetry;
+use signer_protocol::{PayloadCodec, PayloadFormat};
+use signer_service::transport::KafkaTransport;
+use signer_service::{health, signer};
+use signer_service::{Service, DEFAULT_MAX_IN_FLIGHT};
 use std::env;
-use std::time::Duration;
-use tokio_stream::StreamExt;
+use std::net::TcpListener;
+use std::num::NonZeroUsize;
 
 // Use Jemalloc only for musl-64 bits platforms
 #[cfg(all(target_env = "musl", target_pointer_width = "64"))]
 #[global_allocator]
 static ALLOC: jemallocator::Jemalloc = jemallocator::Jemalloc;
 
-struct MsgToSign {
-    // headers
-    pub msg_id: String, //this is general id could be topic+partition_id+offset?
-    pub resp_topic: String,
-    // payload
-    pub msg: String,
-}
-
-impl TryFrom<BorrowedMessage<'_>> for MsgToSign {
-    type Error = anyhow::Error;
-
-    fn try_from(value: BorrowedMessage) -> Result<Self, Self::Error> {
-        let headers = value
-            .headers()
-            .ok_or_else(|| anyhow::Error::msg("expected headers in msg"))?;
-
-        let (key, msg_id) = headers
-            .get_as(0)
-            .ok_or_else(|| anyhow::Error::msg("expected headers to not be empty"))?;
-        if key != "msg_id" {
-            bail!("expected `msg_id` header key at index 0 but found: {}", key)
-        }
-        let msg_id: &str = msg_id?;
-
-        let (key, resp_topic) = headers
-            .get_as(1)
-            .ok_or_else(|| anyhow::Error::msg("expected headers to contains index 1"))?;
-        if key != "resp_topic" {
-            bail!(
-                "expected `resp_topic` header key at index 1 but found {}",
-                key
-            )
-        }
-        let resp_topic: &str = resp_topic?;
-
-        let msg = value
-            .payload()
-            .ok_or_else(|| anyhow::Error::msg("no payload"))?;
-        let msg = String::from_utf8(msg.to_vec())?;
-
-        Ok(Self {
-            msg_id: msg_id.to_string(),
-            resp_topic: resp_topic.to_string(),
-            msg,
-        })
-    }
-}
-
-struct MsgSigned {
-    // headers
-    msg_id: String, //this is general id could be topic+partition_id+offset?
-    resp_id: String,
-    // payload
-    signed_msg: String,
-}
-
-impl MsgSigned {
-    fn from_unsigned(msg_to_sign: MsgToSign) -> MsgSigned {
-        Self {
-            msg_id: msg_to_sign.msg_id,
-            resp_id: uuid::Uuid::new_v4().to_string(),
-            signed_msg: base64::encode(msg_to_sign.msg),
-        }
-    }
-}
-
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    tracing_subscriber::fmt::init();
+    telemetry::init("signer-service")?;
 
     let group_id =
         env::var("SIGNER_SERVICE_GROUP_ID").unwrap_or_else(|_| "signer.v1.service".to_string());
@@ -89,64 +23,136 @@ async fn main() -> anyhow::Result<()> {
         env::var("SIGNER_SERVICE_KAFKA_BROKERS").unwrap_or_else(|_| "127.0.0.1:9092".to_string());
     let req_topic =
         env::var("SIGNER_SERVICE_REQ_TOPIC").unwrap_or_else(|_| "signer.v1".to_string());
+    let key_file = env::var("SIGNER_SERVICE_KEY_FILE")?; //This is required
+    let key_algorithm: signer::Algorithm = env::var("SIGNER_SERVICE_KEY_ALGORITHM")
+        .unwrap_or_else(|_| "ed25519".to_string())
+        .parse()?;
+    let key_id = env::var("SIGNER_SERVICE_KEY_ID").ok();
+    let payload_format: PayloadFormat = env::var("SIGNER_SERVICE_PAYLOAD_FORMAT")
+        .unwrap_or_else(|_| "raw".to_string())
+        .parse()
+        .map_err(anyhow::Error::msg)?;
+    let schema_registry_url = env::var("SIGNER_SERVICE_SCHEMA_REGISTRY_URL").ok();
+    let reply_expired: bool = env::var("SIGNER_SERVICE_REPLY_EXPIRED")
+        .unwrap_or_else(|_| "false".to_string())
+        .parse()?;
+    let dlq_topic = env::var("SIGNER_SERVICE_DLQ_TOPIC").ok();
+    let max_in_flight: NonZeroUsize = env::var("SIGNER_SERVICE_MAX_IN_FLIGHT")
+        .unwrap_or_else(|_| DEFAULT_MAX_IN_FLIGHT.to_string())
+        .parse()
+        .map_err(|_| anyhow::anyhow!("SIGNER_SERVICE_MAX_IN_FLIGHT must be a positive number"))?;
+    let transactional: bool = env::var("SIGNER_SERVICE_TRANSACTIONAL")
+        .unwrap_or_else(|_| "false".to_string())
+        .parse()?;
+    // pod name is stable in StatefulSet and unique among running pods
+    let transactional_id = env::var("SIGNER_SERVICE_TRANSACTIONAL_ID")
+        .or_else(|_| env::var("HOSTNAME").map(|pod| format!("{}.{}", group_id, pod)))
+        .ok();
+    let health_addr =
+        env::var("SIGNER_SERVICE_HEALTH_ADDR").unwrap_or_else(|_| "0.0.0.0:8080".to_string());
 
     tracing::debug!(
         r#"SIGNER_SERVICE_KAFKA_BROKERS: {}
 SIGNER_SERVICE_GROUP_ID: {}
 SIGNER_SERVICE_REQ_TOPIC: {}
+SIGNER_SERVICE_KEY_FILE: {}
+SIGNER_SERVICE_KEY_ALGORITHM: {}
+SIGNER_SERVICE_PAYLOAD_FORMAT: {}
+SIGNER_SERVICE_REPLY_EXPIRED: {}
+SIGNER_SERVICE_DLQ_TOPIC: {:?}
+SIGNER_SERVICE_MAX_IN_FLIGHT: {}
+SIGNER_SERVICE_TRANSACTIONAL: {}
+SIGNER_SERVICE_TRANSACTIONAL_ID: {:?}
+SIGNER_SERVICE_HEALTH_ADDR: {}
 "#,
         brokers,
         group_id,
-        req_topic
+        req_topic,
+        key_file,
+        key_algorithm,
+        payload_format,
+        reply_expired,
+        dlq_topic,
+        max_in_flight,
+        transactional,
+        transactional_id,
+        health_addr
     );
 
-    let producer: FutureProducer = ClientConfig::new()
-        .set("bootstrap.servers", &brokers)
-        .set("message.timeout.ms", "5000")
-        .set_log_level(RDKafkaLogLevel::Debug)
-        .create()?;
-
-    let consumer: StreamConsumer = ClientConfig::new()
-        .set("group.id", &group_id)
-        .set("bootstrap.servers", &brokers)
-        .set("enable.partition.eof", "false")
-        .set("session.timeout.ms", "6000")
-        .set("enable.auto.commit", "true")
-        .set_log_level(RDKafkaLogLevel::Debug)
-        .create()?;
-
-    consumer.subscribe(&[&req_topic])?;
-
-    let mut stream = consumer.stream();
-
-    while let Some(req) = stream.next().await {
-        tracing::trace!("recived req {:?}", req);
-        let req = req?;
+    let signer = signer::from_key_file(&key_file, key_algorithm, key_id)?;
+    tracing::info!(
+        "loaded {} key with id `{}`",
+        signer.algorithm(),
+        signer.key_id()
+    );
 
-        let msg_to_sign = match MsgToSign::try_from(req) {
-            Ok(v) => v,
-            Err(err) => {
-                tracing::error!("unexpected format of request: {:?}", err);
-                continue;
-            }
-        };
+    let codec = match (payload_format, schema_registry_url) {
+        (PayloadFormat::Raw, _) => PayloadCodec::Raw,
+        (format, Some(url)) => PayloadCodec::register(format, &SchemaRegistry::new(&url)?).await?,
+        (format, None) => anyhow::bail!(
+            "SIGNER_SERVICE_SCHEMA_REGISTRY_URL is required for {} payload format",
+            format
+        ),
+    };
+
+    let mut transport = match (transactional, transactional_id) {
+        (false, _) => KafkaTransport::new(&brokers, &group_id, &req_topic, codec)?,
+        (true, Some(transactional_id)) => KafkaTransport::new_transactional(
+            &brokers,
+            &group_id,
+            &req_topic,
+            codec,
+            &transactional_id,
+        )?,
+        (true, None) => anyhow::bail!(
+            "SIGNER_SERVICE_TRANSACTIONAL_ID or HOSTNAME is required in transactional mode"
+        ),
+    };
+    if let Some(dlq_topic) = dlq_topic {
+        transport = transport.with_dlq_topic(dlq_topic);
+    }
+    let service = Service::new(signer)
+        .with_reply_expired(reply_expired)
+        .with_max_in_flight(max_in_flight);
+
+    let health_listener = TcpListener::bind(&health_addr)?;
+    let consumer = transport.consumer();
+    let metrics = service.metrics().clone();
+    let health_server = tokio::spawn(async move {
+        if let Err(err) = health::serve(health_listener, consumer, metrics).await {
+            tracing::error!("health server failed: {}", err);
+        }
+    });
 
-        let resp_topic = msg_to_sign.resp_topic.clone();
-        let signed = MsgSigned::from_unsigned(msg_to_sign);
+    let result = service.run_until(transport, shutdown_signal()).await;
+    // last reference to consumer, dropping it leaves the group
+    health_server.abort();
+    let _ = health_server.await;
+    telemetry::shutdown();
 
-        let record = FutureRecord::<str, _>::to(&resp_topic)
-            .payload(&signed.signed_msg)
-            .headers(
-                OwnedHeaders::new()
-                    .add("msg_id", &signed.msg_id)
-                    .add("resp_id", &signed.resp_id),
-            );
+    result.map_err(Into::into)
+}
 
-        producer
-            .send(record, Duration::from_secs(5))
+/// Ctrl-C or SIGTERM sent by k8s when pod is stopped
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
             .await
-            .map_err(|(err, _ow_msg)| err)?;
+            .expect("failed to listen for ctrl-c");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => (),
+        _ = terminate => (),
     }
-
-    Ok(())
 }
@@ -0,0 +1,79 @@
+//! Prometheus metrics of processed requests, served at `/metrics` by [`crate::health`]
+
+use prometheus::{HistogramOpts, HistogramVec, IntCounter, IntCounterVec, Opts};
+use signer_protocol::metrics::MetricsRegistry;
+
+/// Metrics of single `Service`, registered in its own [`MetricsRegistry`]
+#[derive(Debug, Clone)]
+pub struct Metrics {
+    registry: MetricsRegistry,
+    /// Requests received from transport, including invalid ones
+    pub consumed: IntCounter,
+    /// Responses sent by `status`: `signed` or `failed`
+    pub produced: IntCounterVec,
+    /// Requests not signed by `reason`: `expired`, `invalid_request` or `signing_failed`
+    pub failed: IntCounterVec,
+    /// Time spent signing by `key_id`
+    pub sign_duration: HistogramVec,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        let registry = MetricsRegistry::default();
+        let consumed = registry.register(
+            IntCounter::new(
+                "signer_service_requests_consumed_total",
+                "Requests received from transport",
+            )
+            .expect("valid metric"),
+        );
+        let produced = registry.register(
+            IntCounterVec::new(
+                Opts::new(
+                    "signer_service_responses_produced_total",
+                    "Responses sent by status",
+                ),
+                &["status"],
+            )
+            .expect("valid metric"),
+        );
+        let failed = registry.register(
+            IntCounterVec::new(
+                Opts::new(
+                    "signer_service_requests_failed_total",
+                    "Requests not signed by reason",
+                ),
+                &["reason"],
+            )
+            .expect("valid metric"),
+        );
+        let sign_duration = registry.register(
+            HistogramVec::new(
+                HistogramOpts::new(
+                    "signer_service_sign_duration_seconds",
+                    "Time spent signing by key",
+                )
+                // signing takes microseconds, default buckets start at 5ms
+                .buckets(
+                    prometheus::exponential_buckets(0.000_01, 4.0, 10).expect("valid buckets"),
+                ),
+                &["key_id"],
+            )
+            .expect("valid metric"),
+        );
+
+        Self {
+            registry,
+            consumed,
+            produced,
+            failed,
+            sign_duration,
+        }
+    }
+}
+
+impl Metrics {
+    pub fn registry(&self) -> &MetricsRegistry {
+        &self.registry
+    }
+}
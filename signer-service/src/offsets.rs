@@ -0,0 +1,63 @@
+//! Which offsets are safe to commit when records are finished out of order
+
+use std::collections::{BTreeSet, HashMap};
+
+/// Offsets of records in flight, per partition
+///
+/// Offset to commit for a partition is the lowest one still in flight, or one past the highest
+/// received when nothing is in flight. So commit never moves past a record which isn't finished
+/// and after restart processing continues from the first unfinished record.
+#[derive(Debug, Default)]
+pub struct OffsetTracker {
+    partitions: HashMap<(String, i32), PartitionOffsets>,
+}
+
+#[derive(Debug)]
+struct PartitionOffsets {
+    in_flight: BTreeSet<i64>,
+    /// One past the highest received offset
+    next: i64,
+    committed: i64,
+}
+
+impl OffsetTracker {
+    /// Record at `offset` was received and is being processed
+    pub fn received(&mut self, topic: &str, partition: i32, offset: i64) {
+        // partition is consumed from first received offset, nothing before it to commit
+        let offsets = self
+            .partitions
+            .entry((topic.to_string(), partition))
+            .or_insert_with(|| PartitionOffsets {
+                in_flight: BTreeSet::new(),
+                next: offset,
+                committed: offset,
+            });
+        offsets.in_flight.insert(offset);
+        offsets.next = offsets.next.max(offset + 1);
+    }
+
+    /// Record at `offset` is finished. Returns offset to commit if it moved forward.
+    ///
+    /// After rebalance a record can be received again while its previous delivery is still in
+    /// flight. Whichever finishes first finishes the offset, as the record was processed anyway.
+    pub fn finished(&mut self, topic: &str, partition: i32, offset: i64) -> Option<i64> {
+        let offsets = self.partitions.get_mut(&(topic.to_string(), partition))?;
+        offsets.in_flight.remove(&offset);
+
+        let committable = match offsets.in_flight.iter().next() {
+            Some(lowest) => *lowest,
+            None => offsets.next,
+        };
+        (committable > offsets.committed).then(|| {
+            offsets.committed = committable;
+            committable
+        })
+    }
+
+    /// Offset to commit of every partition, as `(topic, partition, offset)`
+    pub fn committed(&self) -> impl Iterator<Item = (&str, i32, i64)> {
+        self.partitions
+            .iter()
+            .map(|((topic, partition), offsets)| (topic.as_str(), *partition, offsets.committed))
+    }
+}
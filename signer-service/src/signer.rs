@@ -0,0 +1,150 @@
+//! Signing backends
+
+use anyhow::{bail, Context};
+use ed25519_dalek::pkcs8::DecodePrivateKey;
+pub use p256::ecdsa::signature::Error as SignatureError;
+use p256::ecdsa::signature::Signer as _;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Produce detached signatures over message bytes
+pub trait Signer: Send + Sync {
+    /// Id of the key. It is send back with every signature so callers know which public key to use.
+    fn key_id(&self) -> &str;
+
+    fn algorithm(&self) -> Algorithm;
+
+    /// Sign `msg` and return signature bytes
+    fn sign(&self, msg: &[u8]) -> Result<Vec<u8>, SignatureError>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    Ed25519,
+    /// ECDSA over NIST P-256 with SHA-256. Signatures are DER encoded.
+    EcdsaP256,
+}
+
+impl Algorithm {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Algorithm::Ed25519 => "ed25519",
+            Algorithm::EcdsaP256 => "ecdsa-p256",
+        }
+    }
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Algorithm {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ed25519" => Ok(Algorithm::Ed25519),
+            "ecdsa-p256" => Ok(Algorithm::EcdsaP256),
+            other => bail!(
+                "unknown algorithm `{}` expected `ed25519` or `ecdsa-p256`",
+                other
+            ),
+        }
+    }
+}
+
+pub struct Ed25519Signer {
+    key_id: String,
+    key: ed25519_dalek::SigningKey,
+}
+
+impl Ed25519Signer {
+    /// Create signer from PKCS#8 PEM encoded private key
+    pub fn from_pkcs8_pem(key_id: impl Into<String>, pem: &str) -> anyhow::Result<Self> {
+        let key = ed25519_dalek::SigningKey::from_pkcs8_pem(pem)
+            .map_err(|err| anyhow::anyhow!("invalid ed25519 private key: {}", err))?;
+        Ok(Self {
+            key_id: key_id.into(),
+            key,
+        })
+    }
+}
+
+impl Signer for Ed25519Signer {
+    fn key_id(&self) -> &str {
+        &self.key_id
+    }
+
+    fn algorithm(&self) -> Algorithm {
+        Algorithm::Ed25519
+    }
+
+    fn sign(&self, msg: &[u8]) -> Result<Vec<u8>, SignatureError> {
+        let signature = ed25519_dalek::Signer::try_sign(&self.key, msg)?;
+        Ok(signature.to_bytes().to_vec())
+    }
+}
+
+pub struct EcdsaP256Signer {
+    key_id: String,
+    key: p256::ecdsa::SigningKey,
+}
+
+impl EcdsaP256Signer {
+    /// Create signer from PKCS#8 PEM encoded private key
+    pub fn from_pkcs8_pem(key_id: impl Into<String>, pem: &str) -> anyhow::Result<Self> {
+        let key = p256::ecdsa::SigningKey::from_pkcs8_pem(pem)
+            .map_err(|err| anyhow::anyhow!("invalid ecdsa-p256 private key: {}", err))?;
+        Ok(Self {
+            key_id: key_id.into(),
+            key,
+        })
+    }
+}
+
+impl Signer for EcdsaP256Signer {
+    fn key_id(&self) -> &str {
+        &self.key_id
+    }
+
+    fn algorithm(&self) -> Algorithm {
+        Algorithm::EcdsaP256
+    }
+
+    fn sign(&self, msg: &[u8]) -> Result<Vec<u8>, SignatureError> {
+        let signature: p256::ecdsa::Signature = self.key.try_sign(msg)?;
+        Ok(signature.to_der().as_bytes().to_vec())
+    }
+}
+
+/// Load PKCS#8 PEM private key from `path`
+///
+/// When `key_id` is `None` the file stem is used (`keys/prod-1.pem` -> `prod-1`).
+pub fn from_key_file(
+    path: impl AsRef<Path>,
+    algorithm: Algorithm,
+    key_id: Option<String>,
+) -> anyhow::Result<Box<dyn Signer>> {
+    let path = path.as_ref();
+    let pem = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read key file {}", path.display()))?;
+
+    let key_id = match key_id {
+        Some(key_id) => key_id,
+        None => path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .ok_or_else(|| anyhow::anyhow!("can't derive key id from {}", path.display()))?
+            .to_string(),
+    };
+
+    let signer: Box<dyn Signer> = match algorithm {
+        Algorithm::Ed25519 => Box::new(Ed25519Signer::from_pkcs8_pem(key_id, &pem)?),
+        Algorithm::EcdsaP256 => Box::new(EcdsaP256Signer::from_pkcs8_pem(key_id, &pem)?),
+    };
+
+    Ok(signer)
+}
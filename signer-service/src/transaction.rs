@@ -0,0 +1,60 @@
+//! Kafka transaction of a single request, see [`KafkaTransport::new_transactional`]
+//!
+//! Transaction is begun when request is received, response (or dead-lettered record) is produced
+//! in it and it's committed together with offset of the request. Producer calls are behind
+//! [`Transactions`], so the sequence can be tested without broker.
+//!
+//! [`KafkaTransport::new_transactional`]: crate::transport::KafkaTransport::new_transactional
+
+use rdkafka::error::KafkaError;
+use rdkafka::{Offset, TopicPartitionList};
+
+use crate::transport::RecordPos;
+
+/// Transaction operations of producer
+pub trait Transactions {
+    fn begin(&self) -> Result<(), KafkaError>;
+
+    /// Commit `offsets` for consumer group as part of the transaction
+    fn send_offsets(&self, offsets: &TopicPartitionList) -> Result<(), KafkaError>;
+
+    fn commit(&self) -> Result<(), KafkaError>;
+
+    fn abort(&self) -> Result<(), KafkaError>;
+}
+
+/// Commit transaction of request at `pos`, with offset `next` if it moved forward
+///
+/// Failed transaction is aborted, see [`abort`].
+pub fn commit_request<T: Transactions + ?Sized>(
+    txn: &T,
+    pos: &RecordPos,
+    next: Option<i64>,
+) -> Result<(), KafkaError> {
+    let result = next
+        .map(|next| txn.send_offsets(&offset_list(pos, next)?))
+        .unwrap_or(Ok(()))
+        .and_then(|()| txn.commit());
+
+    if let Err(err) = &result {
+        tracing::error!("failed to commit transaction of {:?}", pos);
+        abort(txn, err);
+    }
+    result
+}
+
+/// Abort transaction which failed with `err`, so its response is never visible to
+/// `read_committed` consumers and the request is received again
+pub fn abort<T: Transactions + ?Sized>(txn: &T, err: &KafkaError) {
+    tracing::error!("aborting transaction: {}", err);
+    if let Err(abort_err) = txn.abort() {
+        tracing::error!("failed to abort transaction: {}", abort_err);
+    }
+}
+
+/// Offset `next` of partition of `pos`
+pub(crate) fn offset_list(pos: &RecordPos, next: i64) -> Result<TopicPartitionList, KafkaError> {
+    let mut tpl = TopicPartitionList::new();
+    tpl.add_partition_offset(&pos.topic, pos.partition, Offset::Offset(next))?;
+    Ok(tpl)
+}
@@ -0,0 +1,494 @@
+//! Ways of receiving sign requests and delivering responses
+//!
+//! [`KafkaTransport`] is used in production. [`InProcessTransport`] is fed through channels by
+//! requester running in the same process, for local development and integration tests.
+
+use futures::future::{self, BoxFuture};
+use rdkafka::config::RDKafkaLogLevel;
+use rdkafka::consumer::{CommitMode, Consumer, StreamConsumer};
+use rdkafka::error::KafkaError;
+use rdkafka::producer::{FutureProducer, FutureRecord, Producer};
+use rdkafka::{ClientConfig, Message, Offset, TopicPartitionList};
+use signer_protocol::{MsgResp, MsgToSign, PayloadCodec, ProtocolError};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc::{Receiver, Sender};
+
+use crate::dlq;
+use crate::offsets::OffsetTracker;
+use crate::transaction::{self, offset_list, Transactions};
+
+/// Where response to request is delivered
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplyTo {
+    pub topic: String,
+    /// Partition of shared `topic` requester consumes, any partition when `None`
+    pub partition: Option<i32>,
+}
+
+impl ReplyTo {
+    pub fn of(req: &MsgToSign) -> Self {
+        Self {
+            topic: req.resp_topic().to_string(),
+            partition: req.resp_partition(),
+        }
+    }
+}
+
+/// Request that doesn't follow signer protocol
+#[derive(Debug)]
+pub struct InvalidReq {
+    /// `msg_id` and response destination of request when they could be read, so requester can be
+    /// told
+    pub reply_to: Option<(String, ReplyTo)>,
+    pub err: ProtocolError,
+}
+
+/// Received request, possibly invalid
+pub type Incoming = Result<MsgToSign, InvalidReq>;
+
+/// Next request with receipt to [`Transport::commit`] it, see [`Transport::recv`]
+pub type RecvFuture<'a, R, E> = BoxFuture<'a, Option<Result<(Incoming, R), E>>>;
+
+/// Decode request from Kafka message
+pub fn decode_request<M: Message>(codec: &PayloadCodec, msg: &M) -> Incoming {
+    codec.decode_request(msg).map_err(|err| {
+        let msg_id = signer_protocol::msg_id(msg);
+        let resp_topic = signer_protocol::resp_topic(msg);
+        let resp_partition = signer_protocol::resp_partition(msg);
+        let reply_to = match (msg_id, resp_topic, resp_partition) {
+            (Ok(msg_id), Ok(topic), Ok(partition)) => {
+                let reply_to = ReplyTo {
+                    topic: topic.to_string(),
+                    partition,
+                };
+                Some((msg_id.to_string(), reply_to))
+            }
+            _ => None,
+        };
+        InvalidReq { reply_to, err }
+    })
+}
+
+pub trait Transport: Send + Sync {
+    type Error: std::error::Error + Send + Sync + 'static;
+    /// Identifies received request in [`Transport::commit`]
+    type Receipt: Send + 'static;
+
+    /// Wait for next request. `None` means no more requests will come.
+    ///
+    /// Called again only after previous call finished, but concurrently with `send` and `commit`
+    /// of earlier requests.
+    fn recv(&self) -> RecvFuture<'_, Self::Receipt, Self::Error>;
+
+    /// Deliver `resp` to requester
+    fn send<'a>(
+        &'a self,
+        reply_to: &'a ReplyTo,
+        resp: MsgResp,
+    ) -> BoxFuture<'a, Result<(), Self::Error>>;
+
+    /// Request is finished: its response was delivered or it needs none. Finished request is not
+    /// received again after restart.
+    fn commit(&self, receipt: Self::Receipt) -> BoxFuture<'_, Result<(), Self::Error>>;
+
+    /// How many requests can be in flight, between `recv` and `commit`, at once
+    fn max_in_flight(&self) -> usize {
+        usize::MAX
+    }
+
+    /// Called once when service stops and no request is in flight anymore
+    fn close(&self) -> BoxFuture<'_, Result<(), Self::Error>> {
+        Box::pin(future::ready(Ok(())))
+    }
+}
+
+pub struct KafkaTransport {
+    consumer: Arc<StreamConsumer>,
+    producer: FutureProducer,
+    codec: PayloadCodec,
+    dlq_topic: Option<String>,
+    offsets: Mutex<OffsetTracker>,
+    /// Every request is handled in its own transaction, see [`KafkaTransport::new_transactional`]
+    transactional: bool,
+}
+
+/// How long transaction operations wait for broker
+const TXN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Position of received record
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordPos {
+    pub topic: String,
+    pub partition: i32,
+    pub offset: i64,
+}
+
+impl KafkaTransport {
+    /// Subscribe to `req_topic` as member of `group_id`
+    ///
+    /// Offsets are committed only after request is finished, see [`Transport::commit`], so
+    /// requests are processed at least once.
+    pub fn new(
+        brokers: &str,
+        group_id: &str,
+        req_topic: &str,
+        codec: PayloadCodec,
+    ) -> Result<Self, KafkaError> {
+        let producer = producer_config(brokers).create()?;
+        Self::with_producer(producer, brokers, group_id, req_topic, codec)
+    }
+
+    /// Like [`KafkaTransport::new`] but response (or dead-lettered record) and offset of request
+    /// are committed in single Kafka transaction, so each request has exactly one response visible
+    /// to `read_committed` consumers
+    ///
+    /// `transactional_id` must be stable across restarts of the instance and unique among running
+    /// instances, so restarted instance fences off its previous incarnation.
+    pub fn new_transactional(
+        brokers: &str,
+        group_id: &str,
+        req_topic: &str,
+        codec: PayloadCodec,
+        transactional_id: &str,
+    ) -> Result<Self, KafkaError> {
+        let producer: FutureProducer = producer_config(brokers)
+            .set("transactional.id", transactional_id)
+            .create()?;
+        // aborts transaction left open by previous incarnation
+        producer.init_transactions(TXN_TIMEOUT)?;
+
+        let mut transport = Self::with_producer(producer, brokers, group_id, req_topic, codec)?;
+        transport.transactional = true;
+        Ok(transport)
+    }
+
+    fn with_producer(
+        producer: FutureProducer,
+        brokers: &str,
+        group_id: &str,
+        req_topic: &str,
+        codec: PayloadCodec,
+    ) -> Result<Self, KafkaError> {
+        let consumer: StreamConsumer = ClientConfig::new()
+            .set("group.id", group_id)
+            .set("bootstrap.servers", brokers)
+            .set("enable.partition.eof", "false")
+            .set("session.timeout.ms", "6000")
+            .set("enable.auto.commit", "false")
+            .set_log_level(RDKafkaLogLevel::Debug)
+            .create()?;
+
+        consumer.subscribe(&[req_topic])?;
+
+        Ok(Self {
+            consumer: Arc::new(consumer),
+            producer,
+            codec,
+            dlq_topic: None,
+            offsets: Mutex::default(),
+            transactional: false,
+        })
+    }
+
+    /// Consumer of request topic, its assignment is reported by [`crate::health`]
+    pub fn consumer(&self) -> Arc<StreamConsumer> {
+        Arc::clone(&self.consumer)
+    }
+
+    /// Republish records that can't be decoded to `dlq_topic`, see [`crate::dlq`]
+    pub fn with_dlq_topic(mut self, dlq_topic: impl Into<String>) -> Self {
+        self.dlq_topic = Some(dlq_topic.into());
+        self
+    }
+
+    async fn dead_letter<M: Message>(
+        &self,
+        msg: &M,
+        err: &ProtocolError,
+    ) -> Result<(), KafkaError> {
+        let dlq_topic = match &self.dlq_topic {
+            Some(dlq_topic) => dlq_topic,
+            None => return Ok(()),
+        };
+
+        let mut record =
+            FutureRecord::<[u8], [u8]>::to(dlq_topic).headers(dlq::dead_letter_headers(msg, err));
+        if let Some(key) = msg.key() {
+            record = record.key(key);
+        }
+        if let Some(payload) = msg.payload() {
+            record = record.payload(payload);
+        }
+
+        self.producer
+            .send(record, Duration::from_secs(5))
+            .await
+            .map(drop)
+            .map_err(|(err, _ow_msg)| err)
+    }
+
+    fn transactions(&self) -> KafkaTransactions {
+        KafkaTransactions {
+            producer: self.producer.clone(),
+            consumer: Arc::clone(&self.consumer),
+        }
+    }
+
+    /// Abort transaction of request when its response (or dead-lettered record) wasn't produced
+    async fn abort_failed(&self, produced: Result<(), KafkaError>) -> Result<(), KafkaError> {
+        match produced {
+            Err(err) if self.transactional => {
+                let txn = self.transactions();
+                blocking(move || {
+                    transaction::abort(&txn, &err);
+                    Err(err)
+                })
+                .await
+            }
+            produced => produced,
+        }
+    }
+}
+
+/// Run blocking Kafka call on thread where blocking is allowed
+async fn blocking<T, F>(f: F) -> T
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .unwrap_or_else(|err| std::panic::resume_unwind(err.into_panic()))
+}
+
+/// [`Transactions`] of producer, offsets are committed for group of consumer
+struct KafkaTransactions {
+    producer: FutureProducer,
+    consumer: Arc<StreamConsumer>,
+}
+
+impl Transactions for KafkaTransactions {
+    fn begin(&self) -> Result<(), KafkaError> {
+        self.producer.begin_transaction()
+    }
+
+    fn send_offsets(&self, offsets: &TopicPartitionList) -> Result<(), KafkaError> {
+        let group = self
+            .consumer
+            .group_metadata()
+            .expect("consumer has group.id");
+        self.producer
+            .send_offsets_to_transaction(offsets, &group, TXN_TIMEOUT)
+    }
+
+    fn commit(&self) -> Result<(), KafkaError> {
+        self.producer.commit_transaction(TXN_TIMEOUT)
+    }
+
+    fn abort(&self) -> Result<(), KafkaError> {
+        self.producer.abort_transaction(TXN_TIMEOUT)
+    }
+}
+
+fn producer_config(brokers: &str) -> ClientConfig {
+    let mut config = ClientConfig::new();
+    config
+        .set("bootstrap.servers", brokers)
+        .set("message.timeout.ms", "5000")
+        .set_log_level(RDKafkaLogLevel::Debug);
+    config
+}
+
+impl Transport for KafkaTransport {
+    type Error = KafkaError;
+    type Receipt = RecordPos;
+
+    fn recv(&self) -> RecvFuture<'_, RecordPos, KafkaError> {
+        Box::pin(async move {
+            let req = match self.consumer.recv().await {
+                Ok(req) => req,
+                Err(err) => return Some(Err(err)),
+            };
+            tracing::trace!("recived req {:?}", req);
+            let pos = RecordPos {
+                topic: req.topic().to_string(),
+                partition: req.partition(),
+                offset: req.offset(),
+            };
+            self.offsets
+                .lock()
+                .unwrap()
+                .received(&pos.topic, pos.partition, pos.offset);
+            if self.transactional {
+                if let Err(err) = self.transactions().begin() {
+                    return Some(Err(err));
+                }
+            }
+
+            let incoming = decode_request(&self.codec, &req);
+            if let Err(invalid) = &incoming {
+                // losing the record would be worse than stopping
+                let dead_lettered = self.dead_letter(&req, &invalid.err).await;
+                if let Err(err) = self.abort_failed(dead_lettered).await {
+                    return Some(Err(err));
+                }
+            }
+
+            Some(Ok((incoming, pos)))
+        })
+    }
+
+    fn send<'a>(
+        &'a self,
+        reply_to: &'a ReplyTo,
+        resp: MsgResp,
+    ) -> BoxFuture<'a, Result<(), KafkaError>> {
+        Box::pin(async move {
+            let payload;
+            let record = match &resp {
+                MsgResp::Signed(signed) => {
+                    payload = self.codec.encode_response(signed);
+                    FutureRecord::<str, [u8]>::to(&reply_to.topic)
+                        .payload(&payload)
+                        .headers(signed.headers())
+                }
+                // failures have no payload
+                MsgResp::Failed(failed) => {
+                    FutureRecord::<str, [u8]>::to(&reply_to.topic).headers(failed.headers())
+                }
+            };
+            let record = match reply_to.partition {
+                Some(partition) => record.partition(partition),
+                None => record,
+            };
+
+            let sent = self
+                .producer
+                .send(record, Duration::from_secs(5))
+                .await
+                .map(drop)
+                .map_err(|(err, _ow_msg)| err);
+            self.abort_failed(sent).await
+        })
+    }
+
+    fn commit(&self, pos: RecordPos) -> BoxFuture<'_, Result<(), KafkaError>> {
+        let next = self
+            .offsets
+            .lock()
+            .unwrap()
+            .finished(&pos.topic, pos.partition, pos.offset);
+        if self.transactional {
+            // transaction has to be committed even if offset doesn't move
+            let txn = self.transactions();
+            return Box::pin(blocking(move || {
+                transaction::commit_request(&txn, &pos, next)
+            }));
+        }
+
+        let committed = match next {
+            Some(next) => offset_list(&pos, next)
+                .and_then(|offsets| self.consumer.commit(&offsets, CommitMode::Async)),
+            None => Ok(()), // earlier request of the partition is still in flight
+        };
+        Box::pin(future::ready(committed))
+    }
+
+    /// Producer has single open transaction at a time
+    fn max_in_flight(&self) -> usize {
+        if self.transactional {
+            1
+        } else {
+            usize::MAX
+        }
+    }
+
+    /// Flush producer, commit offsets of finished requests synchronously and unsubscribe
+    ///
+    /// Consumer leaves the group when it's dropped, including the one given to
+    /// [`crate::health`].
+    fn close(&self) -> BoxFuture<'_, Result<(), KafkaError>> {
+        let producer = self.producer.clone();
+        let consumer = Arc::clone(&self.consumer);
+        // offsets were committed with transactions
+        let committed: Vec<_> = if self.transactional {
+            Vec::new()
+        } else {
+            self.offsets
+                .lock()
+                .unwrap()
+                .committed()
+                .map(|(topic, partition, offset)| (topic.to_string(), partition, offset))
+                .collect()
+        };
+        Box::pin(blocking(move || {
+            producer.flush(Duration::from_secs(5));
+            let assignment = consumer.assignment()?;
+            let mut offsets = TopicPartitionList::new();
+            for (topic, partition, offset) in committed {
+                if assignment.find_partition(&topic, partition).is_some() {
+                    offsets.add_partition_offset(&topic, partition, Offset::Offset(offset))?;
+                }
+            }
+            if offsets.count() > 0 {
+                consumer.commit(&offsets, CommitMode::Sync)?;
+            }
+            consumer.unsubscribe();
+            Ok(())
+        }))
+    }
+}
+
+/// Requester of in-process transport is gone
+#[derive(Debug, thiserror::Error)]
+#[error("in-process requester dropped response channel")]
+pub struct RequesterDropped;
+
+/// Transport fed by requester running in the same process
+///
+/// `requests` and `responses` are the other ends of channels given to requester, for example to
+/// `signer_rest_api::transport::InProcessTransport`. `resp_topic` and `resp_partition` of requests are ignored.
+pub struct InProcessTransport {
+    requests: tokio::sync::Mutex<Receiver<MsgToSign>>,
+    responses: Sender<MsgResp>,
+}
+
+impl InProcessTransport {
+    pub fn new(requests: Receiver<MsgToSign>, responses: Sender<MsgResp>) -> Self {
+        Self {
+            requests: tokio::sync::Mutex::new(requests),
+            responses,
+        }
+    }
+}
+
+impl Transport for InProcessTransport {
+    type Error = RequesterDropped;
+    type Receipt = ();
+
+    fn recv(&self) -> RecvFuture<'_, (), RequesterDropped> {
+        Box::pin(async move {
+            let req = self.requests.lock().await.recv().await;
+            req.map(|req| Ok((Ok(req), ())))
+        })
+    }
+
+    fn send<'a>(
+        &'a self,
+        _reply_to: &'a ReplyTo,
+        resp: MsgResp,
+    ) -> BoxFuture<'a, Result<(), RequesterDropped>> {
+        Box::pin(async move {
+            self.responses
+                .send(resp)
+                .await
+                .map_err(|_| RequesterDropped)
+        })
+    }
+
+    /// Nothing to commit, requests are not received again anyway
+    fn commit(&self, _receipt: ()) -> BoxFuture<'_, Result<(), RequesterDropped>> {
+        Box::pin(future::ready(Ok(())))
+    }
+}
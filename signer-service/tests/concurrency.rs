@@ -0,0 +1,44 @@
+mod common;
+
+use std::num::NonZeroUsize;
+use std::time::Duration;
+
+use common::{Event, ScriptedTransport};
+
+/// `n` requests answered slowly
+fn slow_transport(n: u8) -> ScriptedTransport {
+    let reqs = (0..n).map(|i| common::req(&[i])).collect();
+    ScriptedTransport::with_reqs(reqs).with_send_delay(Duration::from_millis(20))
+}
+
+fn committed(event: &Event) -> bool {
+    matches!(event, Event::Committed(_))
+}
+
+#[tokio::test]
+async fn requests_are_processed_concurrently_up_to_limit() {
+    let transport = slow_transport(16);
+    let log = transport.log();
+    common::service()
+        .with_max_in_flight(NonZeroUsize::new(4).unwrap())
+        .run(transport)
+        .await
+        .unwrap();
+
+    assert_eq!(log.max_sending(), 4);
+    assert_eq!(log.count(committed), 16);
+}
+
+#[tokio::test]
+async fn transport_can_lower_limit() {
+    let transport = slow_transport(4).with_max_in_flight(1);
+    let log = transport.log();
+    common::service()
+        .with_max_in_flight(NonZeroUsize::new(4).unwrap())
+        .run(transport)
+        .await
+        .unwrap();
+
+    assert_eq!(log.max_sending(), 1);
+    assert_eq!(log.count(committed), 4);
+}
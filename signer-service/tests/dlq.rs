@@ -0,0 +1,99 @@
+use rdkafka::message::{Headers, OwnedHeaders, OwnedMessage, Timestamp};
+use rdkafka::Message;
+use signer_protocol::{MsgToSign, ProtocolError};
+use signer_service::dlq;
+use std::time::{Duration, SystemTime};
+
+fn kafka_msg(topic: &str, headers: OwnedHeaders) -> OwnedMessage {
+    OwnedMessage::new(
+        Some(b"payload".to_vec()),
+        Some(b"key".to_vec()),
+        topic.to_string(),
+        Timestamp::NotAvailable,
+        3,
+        42,
+        Some(headers),
+    )
+}
+
+fn header_list<H: Headers + ?Sized>(headers: &H) -> Vec<(String, Vec<u8>)> {
+    (0..headers.count())
+        .filter_map(|idx| headers.get(idx))
+        .map(|(name, value)| (name.to_string(), value.to_vec()))
+        .collect()
+}
+
+#[test]
+fn dead_letter_keeps_original_headers_and_adds_origin() {
+    let req = MsgToSign::new(b"hello".to_vec(), "signer.v1.resp0".to_string());
+    let original = req.headers().add("traceparent", "00-abc-def-01");
+    let msg = kafka_msg("signer.v1", original.clone());
+
+    let headers = dlq::dead_letter_headers(&msg, &ProtocolError::MissingHeader("msg_id"));
+    let list = header_list(&headers);
+    assert_eq!(list[..original.count()], header_list(&original)[..]);
+
+    let dlq_headers = &list[original.count()..];
+    assert_eq!(dlq_headers.len(), 4);
+    assert_eq!(
+        dlq_headers[..3],
+        [
+            (dlq::SOURCE_TOPIC.to_string(), b"signer.v1".to_vec()),
+            (dlq::SOURCE_PARTITION.to_string(), b"3".to_vec()),
+            (dlq::SOURCE_OFFSET.to_string(), b"42".to_vec()),
+        ]
+    );
+    assert_eq!(dlq_headers[3].0, dlq::ERROR);
+    assert!(!dlq_headers[3].1.is_empty());
+}
+
+#[test]
+fn replay_restores_original_record() {
+    let req = MsgToSign::new(b"hello".to_vec(), "signer.v1.resp0".to_string());
+    let msg = kafka_msg("signer.v1", req.headers());
+
+    let dead_lettered = kafka_msg(
+        "signer.v1.dlq",
+        dlq::dead_letter_headers(&msg, &ProtocolError::MissingHeader("msg_id")),
+    );
+    assert_eq!(dlq::source_topic(&dead_lettered), Some("signer.v1"));
+
+    let restored = dlq::strip_dead_letter_headers(dead_lettered.headers().unwrap());
+    assert_eq!(header_list(&restored), header_list(&req.headers()));
+}
+
+#[test]
+fn record_without_headers_is_dead_lettered() {
+    let msg = OwnedMessage::new(
+        None,
+        None,
+        "signer.v1".to_string(),
+        Timestamp::NotAvailable,
+        0,
+        7,
+        None,
+    );
+
+    let headers = dlq::dead_letter_headers(&msg, &ProtocolError::MissingHeader("msg_id"));
+    assert_eq!(headers.count(), 4);
+    assert!(header_list(&dlq::strip_dead_letter_headers(&headers)).is_empty());
+}
+
+#[test]
+fn replay_replaces_past_deadline() {
+    let past = SystemTime::now() - Duration::from_secs(3600);
+    let req = MsgToSign::new(b"hello".to_vec(), "signer.v1.resp0".to_string()).with_deadline(past);
+    let msg = kafka_msg("signer.v1", req.headers());
+    let dead_lettered = dlq::dead_letter_headers(&msg, &ProtocolError::MissingHeader("msg_id"));
+
+    let replayed = kafka_msg("signer.v1", dlq::replay_headers(&dead_lettered, None));
+    let replayed = MsgToSign::from_message(&replayed).unwrap();
+    assert_eq!(replayed.msg_id(), req.msg_id());
+    assert_eq!(replayed.deadline(), None);
+
+    let deadline = SystemTime::now() + Duration::from_secs(60);
+    let headers = dlq::replay_headers(&dead_lettered, Some(deadline));
+    assert_eq!(headers.count(), req.headers().count());
+    let replayed = MsgToSign::from_message(&kafka_msg("signer.v1", headers)).unwrap();
+    assert!(!replayed.is_expired());
+}
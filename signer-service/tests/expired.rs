@@ -0,0 +1,55 @@
+mod common;
+
+use std::time::{Duration, SystemTime};
+
+use signer_protocol::{ErrorCode, MsgResp, MsgToSign};
+use signer_service::Service;
+
+fn service(reply_expired: bool) -> Service {
+    common::service().with_reply_expired(reply_expired)
+}
+
+fn expired_req() -> MsgToSign {
+    common::req(b"late").with_deadline(SystemTime::now() - Duration::from_secs(1))
+}
+
+fn fresh_req() -> MsgToSign {
+    common::req(b"hello").with_deadline(SystemTime::now() + Duration::from_secs(60))
+}
+
+fn expired_count(service: &Service) -> u64 {
+    service
+        .metrics()
+        .failed
+        .with_label_values(&["expired"])
+        .get()
+}
+
+#[tokio::test]
+async fn expired_requests_are_skipped_and_counted() {
+    let service = service(false);
+    let fresh = fresh_req();
+
+    let resps = common::run_in_process(&service, vec![expired_req(), fresh.clone()]).await;
+
+    assert_eq!(resps.len(), 1);
+    assert!(matches!(&resps[0], MsgResp::Signed(signed) if signed.msg_id() == fresh.msg_id()));
+    assert_eq!(expired_count(&service), 1);
+}
+
+#[tokio::test]
+async fn expired_requests_can_be_answered() {
+    let service = service(true);
+    let expired = expired_req();
+
+    let resps = common::run_in_process(&service, vec![expired.clone()]).await;
+
+    match &resps[..] {
+        [MsgResp::Failed(failed)] => {
+            assert_eq!(failed.msg_id(), expired.msg_id());
+            assert_eq!(failed.code(), ErrorCode::Expired);
+        }
+        other => panic!("expected single failure, got {:?}", other),
+    }
+    assert_eq!(expired_count(&service), 1);
+}
@@ -0,0 +1,93 @@
+mod common;
+
+use common::ScriptedTransport;
+use rdkafka::message::{OwnedHeaders, OwnedMessage, Timestamp};
+use signer_protocol::{ErrorCode, MsgResp, MsgToSign, PayloadCodec, ProtocolError};
+use signer_service::signer::{Algorithm, SignatureError, Signer};
+use signer_service::transport::{decode_request, Incoming, ReplyTo};
+use signer_service::Service;
+
+/// Signer which key is not available
+struct BrokenSigner;
+
+impl Signer for BrokenSigner {
+    fn key_id(&self) -> &str {
+        "broken"
+    }
+
+    fn algorithm(&self) -> Algorithm {
+        Algorithm::Ed25519
+    }
+
+    fn sign(&self, _msg: &[u8]) -> Result<Vec<u8>, SignatureError> {
+        Err(SignatureError::new())
+    }
+}
+
+async fn run(service: &Service, incoming: Vec<Incoming>) -> Vec<(ReplyTo, MsgResp)> {
+    let transport = ScriptedTransport::new(incoming);
+    let log = transport.log();
+    service.run(transport).await.unwrap();
+    log.sent()
+}
+
+fn kafka_msg(headers: OwnedHeaders, payload: Option<&[u8]>) -> OwnedMessage {
+    OwnedMessage::new(
+        payload.map(<[u8]>::to_vec),
+        None,
+        "signer.v1".to_string(),
+        Timestamp::NotAvailable,
+        0,
+        0,
+        Some(headers),
+    )
+}
+
+#[tokio::test]
+async fn signing_failure_is_reported() {
+    let service = Service::new(Box::new(BrokenSigner));
+    let req =
+        MsgToSign::new(b"hello".to_vec(), "signer.v1.resp".to_string()).with_resp_partition(3);
+
+    let sent = run(&service, vec![Ok(req.clone())]).await;
+
+    match &sent[..] {
+        [(reply_to, MsgResp::Failed(failed))] => {
+            assert_eq!(reply_to.topic, "signer.v1.resp");
+            assert_eq!(reply_to.partition, Some(3));
+            assert_eq!(failed.msg_id(), req.msg_id());
+            assert_eq!(failed.code(), ErrorCode::SigningFailed);
+        }
+        other => panic!("expected single failure, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn invalid_request_is_rejected_when_requester_is_known() {
+    let codec = PayloadCodec::Raw;
+    let without_payload = kafka_msg(
+        OwnedHeaders::new()
+            .add("msg_id", "1")
+            .add("resp_topic", "signer.v1.resp0"),
+        None,
+    );
+    let without_resp_topic = kafka_msg(OwnedHeaders::new().add("msg_id", "2"), Some(b"hello"));
+
+    let incoming = vec![
+        decode_request(&codec, &without_payload),
+        decode_request(&codec, &without_resp_topic),
+    ];
+    let service = Service::new(Box::new(BrokenSigner));
+    let sent = run(&service, incoming).await;
+
+    match &sent[..] {
+        [(reply_to, MsgResp::Failed(failed))] => {
+            assert_eq!(reply_to.topic, "signer.v1.resp0");
+            assert_eq!(reply_to.partition, None);
+            assert_eq!(failed.msg_id(), "1");
+            assert_eq!(failed.code(), ErrorCode::InvalidRequest);
+            assert_eq!(failed.message(), ProtocolError::MissingPayload.to_string());
+        }
+        other => panic!("expected single failure, got {:?}", other),
+    }
+}
@@ -0,0 +1,79 @@
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+
+use rdkafka::error::KafkaError;
+use signer_service::health::{self, Assignment, PartitionLag};
+use signer_service::metrics::Metrics;
+
+/// Assignment report set by test
+#[derive(Default)]
+struct FixedAssignment(Mutex<Vec<PartitionLag>>);
+
+impl Assignment for FixedAssignment {
+    fn assignment(&self) -> Result<Vec<PartitionLag>, KafkaError> {
+        Ok(self.0.lock().unwrap().clone())
+    }
+}
+
+fn spawn_server(assignment: Arc<FixedAssignment>) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(health::serve(listener, assignment, Metrics::default()));
+    addr
+}
+
+/// Status code and body of GET `path`
+async fn get(addr: SocketAddr, path: &str) -> (u16, serde_json::Value) {
+    let request = format!(
+        "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        path
+    );
+    let resp = tokio::task::spawn_blocking(move || {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut resp = String::new();
+        stream.read_to_string(&mut resp).unwrap();
+        resp
+    })
+    .await
+    .unwrap();
+
+    let status = resp[9..12].parse().unwrap();
+    let (_headers, body) = resp.split_once("\r\n\r\n").unwrap();
+    (status, serde_json::from_str(body).unwrap())
+}
+
+#[tokio::test]
+async fn readiness_reports_assignment_and_lag() {
+    let assignment = Arc::new(FixedAssignment::default());
+    let addr = spawn_server(Arc::clone(&assignment));
+
+    let (status, body) = get(addr, "/healthz").await;
+    assert_eq!(status, 200);
+    assert_eq!(body["status"], "ok");
+
+    let (status, body) = get(addr, "/readyz").await;
+    assert_eq!(status, 503);
+    assert_eq!(body["reason"], "no partition assigned");
+
+    assignment.0.lock().unwrap().push(PartitionLag {
+        topic: "signer.v1".to_string(),
+        partition: 3,
+        committed: Some(10),
+        high_watermark: 15,
+        lag: Some(5),
+    });
+    let (status, body) = get(addr, "/readyz").await;
+    assert_eq!(status, 200);
+    assert_eq!(
+        body["assignment"],
+        serde_json::json!([{
+            "topic": "signer.v1",
+            "partition": 3,
+            "committed": 10,
+            "high_watermark": 15,
+            "lag": 5,
+        }])
+    );
+}
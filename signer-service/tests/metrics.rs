@@ -0,0 +1,32 @@
+mod common;
+
+use std::time::{Duration, SystemTime};
+
+#[tokio::test]
+async fn processed_requests_are_counted() {
+    let service = common::service().with_reply_expired(true);
+
+    let expired = common::req(b"late").with_deadline(SystemTime::now() - Duration::from_secs(1));
+    let resps = common::run_in_process(&service, vec![common::req(b"hello"), expired]).await;
+    assert_eq!(resps.len(), 2);
+
+    let metrics = service.metrics();
+    assert_eq!(metrics.consumed.get(), 2);
+    assert_eq!(metrics.produced.with_label_values(&["signed"]).get(), 1);
+    assert_eq!(metrics.produced.with_label_values(&["failed"]).get(), 1);
+    assert_eq!(metrics.failed.with_label_values(&["expired"]).get(), 1);
+    assert_eq!(
+        metrics.failed.with_label_values(&["signing_failed"]).get(),
+        0
+    );
+    let sign_duration = metrics.sign_duration.with_label_values(&["test-key"]);
+    assert_eq!(sign_duration.get_sample_count(), 1);
+
+    let text = metrics.registry().encode();
+    assert!(
+        text.contains("signer_service_requests_consumed_total 2"),
+        "{}",
+        text
+    );
+    assert!(text.contains(r#"signer_service_sign_duration_seconds_count{key_id="test-key"} 1"#));
+}
@@ -0,0 +1,104 @@
+mod common;
+
+use std::num::NonZeroUsize;
+
+use common::{Event, ScriptedTransport};
+use signer_service::offsets::OffsetTracker;
+
+#[test]
+fn in_order_records_are_committed_one_by_one() {
+    let mut tracker = OffsetTracker::default();
+    tracker.received("signer.v1", 0, 10);
+    tracker.received("signer.v1", 0, 11);
+
+    assert_eq!(tracker.finished("signer.v1", 0, 10), Some(11));
+    assert_eq!(tracker.finished("signer.v1", 0, 11), Some(12));
+}
+
+#[test]
+fn commit_does_not_pass_unfinished_record() {
+    let mut tracker = OffsetTracker::default();
+    for offset in 5..8 {
+        tracker.received("signer.v1", 0, offset);
+    }
+
+    assert_eq!(tracker.finished("signer.v1", 0, 6), None);
+    assert_eq!(tracker.finished("signer.v1", 0, 7), None);
+    // 5 was the only one holding the commit back
+    assert_eq!(tracker.finished("signer.v1", 0, 5), Some(8));
+}
+
+#[test]
+fn partitions_are_tracked_separately() {
+    let mut tracker = OffsetTracker::default();
+    tracker.received("signer.v1", 0, 1);
+    tracker.received("signer.v1", 1, 100);
+    tracker.received("signer.v1", 1, 101);
+
+    assert_eq!(tracker.finished("signer.v1", 1, 101), None);
+    assert_eq!(tracker.finished("signer.v1", 0, 1), Some(2));
+    assert_eq!(tracker.finished("signer.v1", 1, 100), Some(102));
+    assert_eq!(tracker.finished("signer.v2", 0, 1), None, "never received");
+}
+
+#[test]
+fn committed_offsets_are_listed_per_partition() {
+    let mut tracker = OffsetTracker::default();
+    tracker.received("signer.v1", 0, 1);
+    tracker.received("signer.v1", 1, 100);
+    tracker.received("signer.v1", 1, 101);
+    tracker.finished("signer.v1", 0, 1);
+    tracker.finished("signer.v1", 1, 101);
+
+    let mut committed: Vec<_> = tracker.committed().collect();
+    committed.sort_unstable();
+    assert_eq!(committed, [("signer.v1", 0, 2), ("signer.v1", 1, 100)]);
+}
+
+#[test]
+fn redelivered_record_does_not_move_commit_back() {
+    let mut tracker = OffsetTracker::default();
+    tracker.received("signer.v1", 0, 1);
+    tracker.received("signer.v1", 0, 2);
+    assert_eq!(tracker.finished("signer.v1", 0, 2), None);
+
+    // rebalance, records are received again from the committed offset
+    tracker.received("signer.v1", 0, 1);
+    assert_eq!(tracker.finished("signer.v1", 0, 1), Some(3));
+    tracker.received("signer.v1", 0, 2);
+    assert_eq!(tracker.finished("signer.v1", 0, 2), None);
+}
+
+async fn run(transport: ScriptedTransport) -> (bool, Vec<Event>) {
+    let log = transport.log();
+    // one by one, so events of requests don't interleave
+    let service = common::service().with_max_in_flight(NonZeroUsize::new(1).unwrap());
+    let result = service.run(transport).await;
+    (result.is_ok(), log.events())
+}
+
+#[tokio::test]
+async fn request_is_committed_after_response_is_sent() {
+    let first = common::req(b"first");
+    let second = common::req(b"second");
+    let expected = vec![
+        Event::Sent(first.msg_id().to_string()),
+        Event::Committed(1),
+        Event::Sent(second.msg_id().to_string()),
+        Event::Committed(2),
+        Event::Closed,
+    ];
+
+    let (ok, events) = run(ScriptedTransport::with_reqs(vec![first, second])).await;
+    assert!(ok);
+    assert_eq!(events, expected);
+}
+
+#[tokio::test]
+async fn request_is_not_committed_when_response_is_lost() {
+    let transport = ScriptedTransport::with_reqs(vec![common::req(b"hello")]).failing_send();
+
+    let (ok, events) = run(transport).await;
+    assert!(!ok);
+    assert!(events.is_empty());
+}
@@ -0,0 +1,35 @@
+mod common;
+
+use std::num::NonZeroUsize;
+use std::time::Duration;
+
+use common::{Event, ScriptedTransport};
+
+#[tokio::test]
+async fn requests_in_flight_are_finished_before_close() {
+    let service = common::service().with_max_in_flight(NonZeroUsize::new(4).unwrap());
+    let transport = ScriptedTransport::endless()
+        .with_recv_delay(Duration::from_millis(5))
+        .with_send_delay(Duration::from_millis(50));
+    let log = transport.log();
+
+    let shutdown = tokio::time::sleep(Duration::from_millis(30));
+    tokio::time::timeout(
+        Duration::from_secs(5),
+        service.run_until(transport, shutdown),
+    )
+    .await
+    .expect("service stopped after shutdown")
+    .unwrap();
+
+    let received = log.received();
+    assert!(received > 0);
+    assert_eq!(log.sent().len(), received);
+    // all requests were committed before transport was closed
+    let events = log.events();
+    assert_eq!(events.last(), Some(&Event::Closed));
+    assert_eq!(
+        log.count(|event| matches!(event, Event::Committed(_))),
+        received
+    );
+}
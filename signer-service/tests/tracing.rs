@@ -0,0 +1,37 @@
+mod common;
+
+use std::time::{Duration, SystemTime};
+
+use opentelemetry::sdk::trace::TracerProvider;
+use opentelemetry::trace::TracerProvider as _;
+use signer_protocol::TraceContext;
+use tracing_subscriber::prelude::*;
+
+const TRACE_ID: &str = "0af7651916cd43dd8448eb211c80319c";
+const PARENT_ID: &str = "b7ad6b7169203331";
+
+#[tokio::test]
+async fn response_continues_trace_of_request() {
+    let provider = TracerProvider::builder().build();
+    let subscriber = tracing_subscriber::registry()
+        .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+    let _default = tracing::subscriber::set_default(subscriber);
+
+    let req = common::req(b"hello")
+        .with_deadline(SystemTime::now() + Duration::from_secs(60))
+        .with_trace_context(TraceContext {
+            traceparent: Some(format!("00-{}-{}-01", TRACE_ID, PARENT_ID)),
+            tracestate: Some("vendor=value".to_string()),
+        });
+    let resps = common::run_in_process(&common::service(), vec![req]).await;
+    let resp = &resps[0];
+
+    let traceparent = resp.trace_context().traceparent.as_deref().unwrap();
+    let parts: Vec<_> = traceparent.split('-').collect();
+    assert_eq!(parts[1], TRACE_ID);
+    assert_ne!(parts[2], PARENT_ID, "span of signer is new child");
+    assert_eq!(
+        resp.trace_context().tracestate.as_deref(),
+        Some("vendor=value")
+    );
+}
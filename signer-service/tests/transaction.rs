@@ -0,0 +1,318 @@
+mod common;
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures::future::{self, BoxFuture};
+
+use rdkafka::admin::{AdminClient, AdminOptions, NewTopic, TopicReplication};
+use rdkafka::client::DefaultClientContext;
+use rdkafka::consumer::{BaseConsumer, Consumer, StreamConsumer};
+use rdkafka::error::KafkaError;
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use rdkafka::types::RDKafkaErrorCode;
+use rdkafka::{ClientConfig, Offset, TopicPartitionList};
+use signer_protocol::{MsgResp, MsgToSign, PayloadCodec};
+use signer_service::transaction::{self, Transactions};
+use signer_service::transport::{KafkaTransport, RecordPos, RecvFuture, ReplyTo, Transport};
+use tokio::sync::oneshot;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Call {
+    Begin,
+    /// Response produced in the transaction
+    Send,
+    /// `(topic, partition, offset)` of sent offsets
+    SendOffsets(Vec<(String, i32, Offset)>),
+    Commit,
+    Abort,
+}
+
+/// Records calls, the one named `fail` fails
+#[derive(Default)]
+struct RecordingTransactions {
+    calls: Mutex<Vec<Call>>,
+    fail: Option<&'static str>,
+}
+
+impl RecordingTransactions {
+    fn failing(fail: &'static str) -> Self {
+        Self {
+            fail: Some(fail),
+            ..Self::default()
+        }
+    }
+
+    fn call(&self, name: &str, call: Call) -> Result<(), KafkaError> {
+        self.calls.lock().unwrap().push(call);
+        match self.fail {
+            Some(fail) if fail == name => {
+                Err(KafkaError::Global(RDKafkaErrorCode::InvalidProducerEpoch))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn calls(&self) -> Vec<Call> {
+        self.calls.lock().unwrap().clone()
+    }
+}
+
+impl Transactions for RecordingTransactions {
+    fn begin(&self) -> Result<(), KafkaError> {
+        self.call("begin", Call::Begin)
+    }
+
+    fn send_offsets(&self, offsets: &TopicPartitionList) -> Result<(), KafkaError> {
+        let offsets = offsets
+            .elements()
+            .iter()
+            .map(|elem| (elem.topic().to_string(), elem.partition(), elem.offset()))
+            .collect();
+        self.call("send_offsets", Call::SendOffsets(offsets))
+    }
+
+    fn commit(&self) -> Result<(), KafkaError> {
+        self.call("commit", Call::Commit)
+    }
+
+    fn abort(&self) -> Result<(), KafkaError> {
+        self.call("abort", Call::Abort)
+    }
+}
+
+fn pos(offset: i64) -> RecordPos {
+    RecordPos {
+        topic: "signer.v1".to_string(),
+        partition: 2,
+        offset,
+    }
+}
+
+#[test]
+fn offset_is_committed_with_transaction() {
+    let txn = RecordingTransactions::default();
+    txn.begin().unwrap();
+    transaction::commit_request(&txn, &pos(41), Some(42)).unwrap();
+
+    assert_eq!(
+        txn.calls(),
+        [
+            Call::Begin,
+            Call::SendOffsets(vec![("signer.v1".to_string(), 2, Offset::Offset(42))]),
+            Call::Commit,
+        ]
+    );
+}
+
+#[test]
+fn transaction_is_committed_when_offset_does_not_move() {
+    // earlier request of the partition is still in flight
+    let txn = RecordingTransactions::default();
+    transaction::commit_request(&txn, &pos(41), None).unwrap();
+    assert_eq!(txn.calls(), [Call::Commit]);
+}
+
+#[test]
+fn failed_transaction_is_aborted() {
+    let txn = RecordingTransactions::failing("send_offsets");
+    assert!(transaction::commit_request(&txn, &pos(41), Some(42)).is_err());
+    assert_eq!(
+        txn.calls(),
+        [
+            Call::SendOffsets(vec![("signer.v1".to_string(), 2, Offset::Offset(42))]),
+            Call::Abort,
+        ]
+    );
+
+    let txn = RecordingTransactions::failing("commit");
+    assert!(transaction::commit_request(&txn, &pos(41), None).is_err());
+    assert_eq!(txn.calls(), [Call::Commit, Call::Abort]);
+}
+
+/// Follows [`KafkaTransport`] in transactional mode: transaction is begun when request is
+/// received, response is produced in it and it's committed with offset past the request
+struct TransactionalTransport {
+    txn: Arc<RecordingTransactions>,
+    reqs: Mutex<VecDeque<MsgToSign>>,
+    next_offset: AtomicI64,
+}
+
+impl TransactionalTransport {
+    fn new(txn: Arc<RecordingTransactions>, reqs: usize) -> Self {
+        Self {
+            txn,
+            reqs: Mutex::new((0..reqs).map(|_| common::req(b"hello")).collect()),
+            next_offset: AtomicI64::new(0),
+        }
+    }
+}
+
+impl Transport for TransactionalTransport {
+    type Error = KafkaError;
+    type Receipt = RecordPos;
+
+    fn recv(&self) -> RecvFuture<'_, RecordPos, KafkaError> {
+        // like Kafka consumer, receives only when polled
+        Box::pin(async move {
+            let req = self.reqs.lock().unwrap().pop_front()?;
+            if let Err(err) = self.txn.begin() {
+                return Some(Err(err));
+            }
+            let pos = pos(self.next_offset.fetch_add(1, Ordering::SeqCst));
+            Some(Ok((Ok(req), pos)))
+        })
+    }
+
+    fn send<'a>(
+        &'a self,
+        _reply_to: &'a ReplyTo,
+        _resp: MsgResp,
+    ) -> BoxFuture<'a, Result<(), KafkaError>> {
+        let sent = self.txn.call("send", Call::Send);
+        if let Err(err) = &sent {
+            transaction::abort(&*self.txn, err);
+        }
+        Box::pin(future::ready(sent))
+    }
+
+    fn commit(&self, pos: RecordPos) -> BoxFuture<'_, Result<(), KafkaError>> {
+        // requests are processed one by one, so offset always moves
+        let next = Some(pos.offset + 1);
+        Box::pin(future::ready(transaction::commit_request(
+            &*self.txn, &pos, next,
+        )))
+    }
+
+    fn max_in_flight(&self) -> usize {
+        1
+    }
+}
+
+#[tokio::test]
+async fn each_request_is_answered_and_committed_in_own_transaction() {
+    let txn = Arc::new(RecordingTransactions::default());
+    let transport = TransactionalTransport::new(Arc::clone(&txn), 2);
+    common::service().run(transport).await.unwrap();
+
+    let offset =
+        |offset| Call::SendOffsets(vec![("signer.v1".to_string(), 2, Offset::Offset(offset))]);
+    assert_eq!(
+        txn.calls(),
+        [
+            Call::Begin,
+            Call::Send,
+            offset(1),
+            Call::Commit,
+            Call::Begin,
+            Call::Send,
+            offset(2),
+            Call::Commit,
+        ]
+    );
+}
+
+#[tokio::test]
+async fn transaction_is_aborted_when_response_is_not_sent() {
+    let txn = Arc::new(RecordingTransactions::failing("send"));
+    let transport = TransactionalTransport::new(Arc::clone(&txn), 2);
+    assert!(common::service().run(transport).await.is_err());
+    assert_eq!(txn.calls(), [Call::Begin, Call::Send, Call::Abort]);
+}
+
+/// Needs broker with transactions at `SIGNER_TEST_KAFKA_BROKERS` (`127.0.0.1:9092`), run with
+/// `cargo test -p signer-service --test transaction -- --ignored`
+#[tokio::test]
+#[ignore]
+async fn request_is_answered_and_committed_in_one_transaction() {
+    let brokers =
+        std::env::var("SIGNER_TEST_KAFKA_BROKERS").unwrap_or_else(|_| "127.0.0.1:9092".to_string());
+    let run_id = uuid::Uuid::new_v4();
+    let req_topic = format!("signer-test.{}", run_id);
+    let resp_topic = format!("signer-test.{}.resp", run_id);
+    let group_id = format!("signer-test.{}", run_id);
+
+    let mut config = ClientConfig::new();
+    config.set("bootstrap.servers", &brokers);
+    let admin: AdminClient<DefaultClientContext> = config.create().unwrap();
+    let topics =
+        [&req_topic, &resp_topic].map(|topic| NewTopic::new(topic, 1, TopicReplication::Fixed(1)));
+    for result in admin
+        .create_topics(&topics, &AdminOptions::new())
+        .await
+        .unwrap()
+    {
+        result.unwrap();
+    }
+
+    let req = MsgToSign::new(b"hello".to_vec(), resp_topic.clone());
+    let payload = PayloadCodec::Raw.encode_request(&req);
+    let producer: FutureProducer = config.create().unwrap();
+    producer
+        .send(
+            FutureRecord::<str, [u8]>::to(&req_topic)
+                .headers(req.headers())
+                .payload(&payload),
+            Duration::from_secs(5),
+        )
+        .await
+        .unwrap();
+
+    let transport = KafkaTransport::new_transactional(
+        &brokers,
+        &group_id,
+        &req_topic,
+        PayloadCodec::Raw,
+        &format!("{}.0", group_id),
+    )
+    .unwrap();
+    // producer has single open transaction at a time
+    assert_eq!(transport.max_in_flight(), 1);
+    let service = common::service();
+    let (stop_tx, stop_rx) = oneshot::channel::<()>();
+    let running = tokio::spawn(async move {
+        service
+            .run_until(transport, async {
+                let _ = stop_rx.await;
+            })
+            .await
+    });
+
+    let resp_consumer: StreamConsumer = config
+        .clone()
+        .set("group.id", format!("{}.resp", group_id))
+        .set("auto.offset.reset", "earliest")
+        .set("isolation.level", "read_committed")
+        .create()
+        .unwrap();
+    resp_consumer.subscribe(&[&resp_topic]).unwrap();
+    let resp = tokio::time::timeout(Duration::from_secs(30), resp_consumer.recv())
+        .await
+        .expect("response is committed")
+        .unwrap();
+    match PayloadCodec::Raw.decode_resp(&resp).unwrap() {
+        MsgResp::Signed(signed) => assert_eq!(signed.msg_id(), req.msg_id()),
+        MsgResp::Failed(failed) => panic!("request failed: {:?}", failed),
+    }
+
+    let _ = stop_tx.send(());
+    running.await.unwrap().unwrap();
+
+    let group_consumer: BaseConsumer = config.clone().set("group.id", &group_id).create().unwrap();
+    let mut partition = TopicPartitionList::new();
+    partition.add_partition(&req_topic, 0);
+    let committed = group_consumer
+        .committed_offsets(partition, Duration::from_secs(5))
+        .unwrap();
+    assert_eq!(
+        committed.find_partition(&req_topic, 0).unwrap().offset(),
+        Offset::Offset(1)
+    );
+
+    admin
+        .delete_topics(&[&req_topic, &resp_topic], &AdminOptions::new())
+        .await
+        .unwrap();
+}